@@ -16,6 +16,62 @@ fn insert(c: &mut Criterion) {
     });
 }
 
+fn insert_at_tail(c: &mut Criterion) {
+    c.bench_function("TreeIndex: insert_at_tail", |b| {
+        b.iter_custom(|iters| {
+            let treeindex: TreeIndex<u64, u64> = TreeIndex::default();
+            let start = Instant::now();
+            for i in 0..iters {
+                assert!(treeindex.insert_at_tail(i, i).is_ok());
+            }
+            start.elapsed()
+        })
+    });
+}
+
+fn from_iter(c: &mut Criterion) {
+    c.bench_function("TreeIndex: from_iter", |b| {
+        b.iter_custom(|iters| {
+            let start = Instant::now();
+            let treeindex: TreeIndex<u64, u64> = (0..iters).map(|i| (i, i)).collect();
+            let elapsed = start.elapsed();
+            assert_eq!(treeindex.len() as u64, iters);
+            elapsed
+        })
+    });
+}
+
+fn insert_buffered(c: &mut Criterion) {
+    c.bench_function("TreeIndex: insert_buffered", |b| {
+        b.iter_custom(|iters| {
+            let treeindex: TreeIndex<u64, u64> = TreeIndex::default();
+            let start = Instant::now();
+            let mut buffer = treeindex.insert_buffered(64);
+            for i in 0..iters {
+                // Bursts of adjacent keys scattered across the key space.
+                let k = (i % 64) + (i / 64).wrapping_mul(0x9E37_79B9) % (1 << 32) * 64;
+                buffer.insert(k, i);
+            }
+            drop(buffer);
+            start.elapsed()
+        })
+    });
+}
+
+fn insert_burst(c: &mut Criterion) {
+    c.bench_function("TreeIndex: insert, burst", |b| {
+        b.iter_custom(|iters| {
+            let treeindex: TreeIndex<u64, u64> = TreeIndex::default();
+            let start = Instant::now();
+            for i in 0..iters {
+                let k = (i % 64) + (i / 64).wrapping_mul(0x9E37_79B9) % (1 << 32) * 64;
+                let _result = treeindex.insert(k, i);
+            }
+            start.elapsed()
+        })
+    });
+}
+
 fn insert_rev(c: &mut Criterion) {
     c.bench_function("TreeIndex: insert, rev", |b| {
         b.iter_custom(|iters| {
@@ -64,5 +120,50 @@ fn peek(c: &mut Criterion) {
     });
 }
 
-criterion_group!(tree_index, insert, insert_rev, iter_with, peek);
+fn peek_pinned(c: &mut Criterion) {
+    c.bench_function("TreeIndex: peek, pinned", |b| {
+        b.iter_custom(|iters| {
+            let treeindex: TreeIndex<u64, u64> = TreeIndex::default();
+            for i in 0..iters {
+                assert!(treeindex.insert(i, i).is_ok());
+            }
+            let start = Instant::now();
+            let mut pinned = treeindex.pinned(256);
+            for i in 0..iters {
+                assert_eq!(pinned.peek(&i), Some(&i));
+            }
+            start.elapsed()
+        })
+    });
+}
+
+fn peek_with(c: &mut Criterion) {
+    c.bench_function("TreeIndex: peek_with", |b| {
+        b.iter_custom(|iters| {
+            let treeindex: TreeIndex<u64, u64> = TreeIndex::default();
+            for i in 0..iters {
+                assert!(treeindex.insert(i, i).is_ok());
+            }
+            let start = Instant::now();
+            for i in 0..iters {
+                assert_eq!(treeindex.peek_with(&i, |_, v| *v), Some(i));
+            }
+            start.elapsed()
+        })
+    });
+}
+
+criterion_group!(
+    tree_index,
+    insert,
+    insert_at_tail,
+    from_iter,
+    insert_buffered,
+    insert_burst,
+    insert_rev,
+    iter_with,
+    peek,
+    peek_pinned,
+    peek_with
+);
 criterion_main!(tree_index);
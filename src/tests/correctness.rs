@@ -1799,19 +1799,23 @@ mod hashcache_test {
 #[cfg(test)]
 mod treeindex_test {
     use crate::ebr::Guard;
-    use crate::tree_index::{Iter, Range};
+    use crate::tree_index::{DescentProfile, Diff, InsertError, Iter, Range, ScanStatus};
     use crate::TreeIndex;
     use proptest::prelude::*;
     use proptest::strategy::ValueTree;
     use proptest::test_runner::TestRunner;
+    use rand::seq::SliceRandom;
+    use rand::{thread_rng, Rng};
     use sdd::suspend;
-    use std::collections::BTreeSet;
-    use std::ops::RangeInclusive;
-    use std::panic::UnwindSafe;
+    use std::collections::{BTreeMap, BTreeSet};
+    use std::ops::{Bound, RangeBounds, RangeInclusive};
+    use std::panic::{catch_unwind, AssertUnwindSafe, UnwindSafe};
+    use std::rc::Rc;
     use std::sync::atomic::Ordering::{Acquire, Relaxed, Release};
     use std::sync::atomic::{AtomicBool, AtomicUsize};
-    use std::sync::{Arc, Barrier};
+    use std::sync::{Arc, Barrier, Mutex};
     use std::thread;
+    use std::time::{Duration, Instant};
     use tokio::sync::Barrier as AsyncBarrier;
     use tokio::task;
 
@@ -2545,45 +2549,2774 @@ mod treeindex_test {
         );
     }
 
-    proptest! {
-        #[cfg_attr(miri, ignore)]
-        #[test]
-        fn prop_remove_range(lower in 0_usize..4096_usize, range in 0_usize..4096_usize) {
-            let remove_range = lower..lower + range;
-            let insert_range = (256_usize, 4095_usize);
-            let tree = TreeIndex::default();
-            for k in insert_range.0..=insert_range.1 {
-                prop_assert!(tree.insert(k, k).is_ok());
+    #[test]
+    fn compare_and_swap_many() {
+        let range = if cfg!(miri) { 64 } else { 1024 };
+        let tree: TreeIndex<usize, usize> = TreeIndex::default();
+        for k in 0..range {
+            assert!(tree.insert(k, k).is_ok());
+        }
+
+        let updates = (0..range + 4)
+            .map(|k| {
+                if k % 2 == 0 {
+                    (k, k, k + 1)
+                } else {
+                    (k, k + 1, k)
+                }
+            })
+            .collect::<Vec<_>>();
+        let result = tree.compare_and_swap_many(&updates);
+        assert_eq!(result.len(), range + 4);
+        for (k, success) in result.into_iter().enumerate() {
+            assert_eq!(success, k % 2 == 0 && k < range, "{k}");
+        }
+        for k in 0..range {
+            let expected = if k % 2 == 0 { k + 1 } else { k };
+            assert_eq!(tree.peek_with(&k, |_, v| *v), Some(expected));
+        }
+        assert_eq!(tree.len(), range);
+
+        // Unsorted updates, and updates of the same key in the given order.
+        assert_eq!(
+            tree.compare_and_swap_many(&[(3, 3, 5), (1, 1, 9), (3, 5, 3), (1, 9, 1), (1, 9, 3)]),
+            [true, true, true, true, false]
+        );
+        assert_eq!(tree.peek_with(&1, |_, v| *v), Some(1));
+        assert_eq!(tree.peek_with(&3, |_, v| *v), Some(3));
+
+        let num_threads = if cfg!(miri) { 2 } else { 8 };
+        let tree = Arc::new(tree);
+        let barrier = Arc::new(Barrier::new(num_threads + 1));
+        let mut thread_handles = Vec::with_capacity(num_threads);
+        for thread_id in 0..num_threads {
+            let tree_clone = tree.clone();
+            let barrier_clone = barrier.clone();
+            thread_handles.push(thread::spawn(move || {
+                barrier_clone.wait();
+                for k in (thread_id..range).step_by(num_threads) {
+                    let v = tree_clone.peek_with(&k, |_, v| *v).unwrap();
+                    assert_eq!(tree_clone.compare_and_swap_many(&[(k, v, v + 1)]), [true]);
+                }
+            }));
+        }
+        barrier.wait();
+        for _ in 0..4 {
+            for k in 0..range {
+                assert!(tree.contains(&k), "{k}");
             }
-            if usize::BITS == 32 {
-                prop_assert_eq!(tree.depth(), 4);
-            } else {
-                prop_assert_eq!(tree.depth(), 3);
+        }
+        for handle in thread_handles {
+            handle.join().unwrap();
+        }
+        for k in 0..range {
+            let expected = if k % 2 == 0 { k + 2 } else { k + 1 };
+            assert_eq!(tree.peek_with(&k, |_, v| *v), Some(expected));
+        }
+        assert_eq!(tree.len(), range);
+    }
+
+    #[test]
+    fn for_each_leafwise() {
+        let range = if cfg!(miri) { 64 } else { 4096 };
+        let tree: TreeIndex<usize, usize> = TreeIndex::default();
+        tree.for_each_leafwise(|_| unreachable!());
+
+        for k in (0..range).rev() {
+            assert!(tree.insert(k, k * 2).is_ok());
+        }
+        tree.remove_range(range / 4..range / 2);
+
+        let mut num_leaves = 0;
+        let mut entries = Vec::new();
+        tree.for_each_leafwise(|leaf_entries| {
+            assert!(!leaf_entries.is_empty());
+            assert!(leaf_entries.windows(2).all(|w| w[0].0 < w[1].0));
+            num_leaves += 1;
+            entries.extend_from_slice(leaf_entries);
+        });
+        assert!(num_leaves > 1);
+
+        let guard = Guard::new();
+        let expected = tree.iter(&guard).map(|(k, v)| (*k, *v)).collect::<Vec<_>>();
+        assert_eq!(entries, expected);
+        assert_eq!(entries.len(), range - range / 4);
+    }
+
+    #[test]
+    fn insert_at_tail() {
+        let range = if cfg!(miri) { 64 } else { 4096 };
+        let tree: TreeIndex<usize, usize> = TreeIndex::default();
+        for k in (0..range).filter(|k| k % 3 != 0) {
+            assert!(tree.insert_at_tail(k, k).is_ok());
+        }
+        for k in (0..range).filter(|k| k % 3 == 0) {
+            // Out-of-order keys fall back to `insert`.
+            assert!(tree.insert_at_tail(k, k).is_ok());
+        }
+        assert_eq!(tree.insert_at_tail(range - 1, 0), Err((range - 1, 0)));
+        assert_eq!(tree.insert_at_tail(0, 1), Err((0, 1)));
+        tree.remove_range(range / 2..);
+        assert!(tree.insert_at_tail(range / 2, range / 2).is_ok());
+
+        let guard = Guard::new();
+        assert!(tree
+            .iter(&guard)
+            .map(|(k, v)| (*k, *v))
+            .eq((0..=range / 2).map(|k| (k, k))));
+
+        let num_threads = if cfg!(miri) { 2 } else { 8 };
+        let tree: Arc<TreeIndex<usize, usize>> = Arc::new(TreeIndex::default());
+        let barrier = Arc::new(Barrier::new(num_threads));
+        let mut thread_handles = Vec::with_capacity(num_threads);
+        for thread_id in 0..num_threads {
+            let tree_clone = tree.clone();
+            let barrier_clone = barrier.clone();
+            thread_handles.push(thread::spawn(move || {
+                barrier_clone.wait();
+                for k in (thread_id..range).step_by(num_threads) {
+                    assert!(tree_clone.insert_at_tail(k, k).is_ok());
+                }
+            }));
+        }
+        for handle in thread_handles {
+            handle.join().unwrap();
+        }
+        for k in 0..range {
+            assert_eq!(tree.peek_with(&k, |_, v| *v), Some(k));
+        }
+        assert!(tree.iter(&guard).map(|(k, _)| *k).eq(0..range));
+    }
+
+    #[test]
+    fn window() {
+        let range = if cfg!(miri) { 64 } else { 1024 };
+        let tree: TreeIndex<usize, usize> = TreeIndex::default();
+        for k in 0..range {
+            assert!(tree.insert(k * 2, k).is_ok());
+        }
+
+        let guard = Guard::new();
+        let keys = |entries: Vec<(&usize, &usize)>| {
+            entries.into_iter().map(|(k, _)| *k).collect::<Vec<_>>()
+        };
+        assert_eq!(keys(tree.window(&0, 4, 2, &guard)), vec![0, 2, 4]);
+        assert_eq!(keys(tree.window(&3, 4, 0, &guard)), vec![0, 2]);
+        assert_eq!(
+            keys(tree.window(&(range * 2 - 2), 2, 4, &guard)),
+            vec![range * 2 - 6, range * 2 - 4, range * 2 - 2]
+        );
+        assert_eq!(
+            keys(tree.window(&(range * 2), 2, 4, &guard)),
+            vec![range * 2 - 4, range * 2 - 2]
+        );
+        assert!(tree.window(&0, 0, 0, &guard).len() == 1);
+        assert!(tree.window(&1, 0, 0, &guard).is_empty());
+
+        for k in (0..range * 2).step_by(7) {
+            let before = k % 37;
+            let after = k % 23;
+            let expected = (0..range * 2)
+                .step_by(2)
+                .filter(|e| *e < k)
+                .rev()
+                .take(before)
+                .collect::<Vec<_>>()
+                .into_iter()
+                .rev()
+                .chain((k % 2 == 0).then_some(k))
+                .chain((0..range * 2).step_by(2).filter(|e| *e > k).take(after))
+                .collect::<Vec<_>>();
+            assert_eq!(keys(tree.window(&k, before, after, &guard)), expected);
+        }
+
+        let empty: TreeIndex<usize, usize> = TreeIndex::default();
+        assert!(empty.window(&0, 4, 4, &guard).is_empty());
+    }
+
+    #[test]
+    fn insert_into() {
+        let range = if cfg!(miri) { 64 } else { 1024 };
+        let tree: TreeIndex<usize, usize> = TreeIndex::default();
+        for k in 0..range {
+            let mut displaced = None;
+            assert!(tree.insert_into(k, k, &mut displaced));
+            assert!(displaced.is_none());
+        }
+        for k in 0..range {
+            let mut displaced = None;
+            assert!(!tree.insert_into(k, k + 1, &mut displaced));
+            assert_eq!(displaced, Some(k));
+        }
+        assert_eq!(tree.len(), range);
+        for k in 0..range {
+            assert_eq!(tree.peek_with(&k, |_, v| *v), Some(k + 1));
+        }
+
+        let num_threads = if cfg!(miri) { 2 } else { 8 };
+        let tree: Arc<TreeIndex<usize, usize>> = Arc::new(TreeIndex::default());
+        let barrier = Arc::new(Barrier::new(num_threads));
+        let mut thread_handles = Vec::with_capacity(num_threads);
+        for _ in 0..num_threads {
+            let tree = tree.clone();
+            let barrier = barrier.clone();
+            thread_handles.push(thread::spawn(move || {
+                barrier.wait();
+                let mut num_inserted = 0;
+                for k in 0..range {
+                    let mut displaced = None;
+                    if tree.insert_into(k, k, &mut displaced) {
+                        assert!(displaced.is_none());
+                        num_inserted += 1;
+                    } else {
+                        assert_eq!(displaced, Some(k));
+                    }
+                }
+                num_inserted
+            }));
+        }
+        let num_inserted = thread_handles
+            .into_iter()
+            .map(|t| t.join().unwrap())
+            .sum::<usize>();
+        assert_eq!(num_inserted, range);
+        assert_eq!(tree.len(), range);
+    }
+
+    #[cfg(debug_assertions)]
+    #[test]
+    fn debug_iter_physical() {
+        let range = if cfg!(miri) { 64 } else { 1024 };
+        let tree: TreeIndex<usize, usize> = TreeIndex::default();
+        for k in 0..range {
+            assert!(tree.insert(k, k).is_ok());
+        }
+
+        // Leaves split by ascending insertions have their entries distributed in key order.
+        let guard = Guard::new();
+        let physical = tree
+            .debug_iter_physical(&guard)
+            .map(|e| e.map(|(k, _)| *k))
+            .collect::<Vec<_>>();
+        assert_eq!(physical, (0..range).map(Some).collect::<Vec<_>>());
+
+        // Removed entries keep occupying their slots.
+        for k in (0..range).step_by(3) {
+            assert!(tree.remove(&k));
+        }
+        let physical = tree
+            .debug_iter_physical(&guard)
+            .map(|e| e.map(|(k, _)| *k))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            physical,
+            (0..range)
+                .map(|k| (k % 3 != 0).then_some(k))
+                .collect::<Vec<_>>()
+        );
+        assert!(tree
+            .debug_iter_physical(&guard)
+            .flatten()
+            .map(|(k, _)| k)
+            .eq(tree.iter(&guard).map(|(k, _)| k)));
+    }
+
+    #[test]
+    fn range_selectivity() {
+        let range: u32 = if cfg!(miri) { 256 } else { 4096 };
+        let ascending: TreeIndex<u32, u32> = TreeIndex::default();
+        let random: TreeIndex<u32, u32> = TreeIndex::default();
+        let mut rng = thread_rng();
+        let mut keys = (0..range).collect::<Vec<_>>();
+        for k in &keys {
+            assert!(ascending.insert(*k, *k).is_ok());
+        }
+        keys.shuffle(&mut rng);
+        for k in &keys {
+            assert!(random.insert(*k, *k).is_ok());
+        }
+
+        // Leaves are evenly filled if keys are inserted in ascending order.
+        for (tree, tolerance) in [(&ascending, 0.05), (&random, 0.25)] {
+            assert!((tree.range_selectivity(..) - 1.0).abs() < f64::EPSILON);
+            assert!(tree.range_selectivity(range..).abs() < f64::EPSILON);
+            for _ in 0..64 {
+                let start = rng.gen_range(0..range);
+                let end = rng.gen_range(start..=range);
+                let expected = f64::from(end - start) / f64::from(range);
+                let estimate = tree.range_selectivity(start..end);
+                assert!((0.0..=1.0).contains(&estimate));
+                assert!(
+                    (estimate - expected).abs() < tolerance,
+                    "{start}..{end}: {estimate} {expected}"
+                );
             }
-            tree.remove_range(remove_range.clone());
-            if remove_range.contains(&insert_range.0) && remove_range.contains(&insert_range.1) {
-                prop_assert!(tree.is_empty());
+        }
+    }
+
+    #[test]
+    fn rebuild_from_leaves() {
+        let range = if cfg!(miri) { 64 } else { 4096 };
+        let tree: TreeIndex<usize, usize> = TreeIndex::default();
+        tree.rebuild_from_leaves();
+        assert!(tree.is_empty());
+
+        let mut keys = (0..range).collect::<Vec<_>>();
+        keys.shuffle(&mut thread_rng());
+        for k in &keys {
+            assert!(tree.insert(*k, *k).is_ok());
+        }
+        for k in keys.iter().filter(|k| *k % 4 != 0) {
+            assert!(tree.remove(k));
+        }
+        let depth = tree.depth();
+
+        tree.rebuild_from_leaves();
+        assert!(tree.depth() <= depth);
+        assert_eq!(tree.len(), range / 4);
+        let guard = Guard::new();
+        assert!(tree
+            .iter(&guard)
+            .map(|(k, v)| (*k, *v))
+            .eq((0..range).step_by(4).map(|k| (k, k))));
+
+        // The rebuilt tree is fully functional.
+        for k in (0..range).filter(|k| k % 4 != 0) {
+            assert!(tree.insert(k, k).is_ok());
+        }
+        assert_eq!(tree.len(), range);
+        for k in 0..range {
+            assert_eq!(tree.peek_with(&k, |_, v| *v), Some(k));
+        }
+        assert!(tree.validate());
+
+        // Internal nodes with corrupted separators are rebuilt from the intact leaves.
+        tree.scramble_separators();
+        assert!(!tree.validate());
+        assert!((0..range).any(|k| !tree.contains(&k)));
+        tree.rebuild_from_leaves();
+        assert!(tree.validate());
+        assert!(tree
+            .iter(&guard)
+            .map(|(k, v)| (*k, *v))
+            .eq((0..range).map(|k| (k, k))));
+        for k in 0..range {
+            assert_eq!(tree.peek_with(&k, |_, v| *v), Some(k));
+        }
+    }
+
+    #[test]
+    fn take_first_last() {
+        let range = if cfg!(miri) { 64 } else { 1024 };
+        let tree: TreeIndex<usize, usize> = TreeIndex::default();
+        assert!(tree.take_first(4).is_empty());
+        assert!(tree.take_last(4).is_empty());
+
+        let mut keys = (0..range).collect::<Vec<_>>();
+        keys.shuffle(&mut thread_rng());
+        for k in &keys {
+            assert!(tree.insert(*k, *k).is_ok());
+        }
+        assert!(tree.take_first(0).is_empty());
+        assert!(tree.take_last(0).is_empty());
+        for len in [1, 7, range / 3, range, range + 1] {
+            let expected = (0..range).map(|k| (k, k)).collect::<Vec<_>>();
+            assert_eq!(tree.take_first(len), expected[..len.min(range)]);
+            assert_eq!(tree.take_last(len), expected[range - len.min(range)..range]);
+        }
+
+        // Empty the rightmost leaves.
+        tree.remove_range(range / 2..);
+        for len in [1, range / 4, range] {
+            let expected = (0..range / 2).map(|k| (k, k)).collect::<Vec<_>>();
+            assert_eq!(
+                tree.take_last(len),
+                expected[range / 2 - len.min(range / 2)..range / 2]
+            );
+        }
+    }
+
+    #[test]
+    fn rename() {
+        let range = if cfg!(miri) { 64 } else { 4096 };
+        let num_readers = if cfg!(miri) { 2 } else { 4 };
+        let tree: Arc<TreeIndex<usize, usize>> = Arc::new(TreeIndex::default());
+        for k in 0..range {
+            assert!(tree.insert(k, k).is_ok());
+        }
+        assert!(tree.insert(range, range).is_ok());
+        assert_eq!(tree.rename(&0, range), Err((range, None)));
+        assert!(tree.remove(&range));
+        assert_eq!(tree.rename(&(range * 2), range * 3), Ok(false));
+
+        let finished = Arc::new(AtomicBool::new(false));
+        let barrier = Arc::new(Barrier::new(num_readers + 1));
+        let mut thread_handles = Vec::with_capacity(num_readers);
+        for _ in 0..num_readers {
+            let tree = tree.clone();
+            let finished = finished.clone();
+            let barrier = barrier.clone();
+            thread_handles.push(thread::spawn(move || {
+                barrier.wait();
+                while !finished.load(Acquire) {
+                    for k in 0..range {
+                        // `to` is checked first as keys are only moved from `k` to `k + range`.
+                        let to = tree.peek_with(&(k + range), |_, v| *v);
+                        let from = tree.peek_with(&k, |_, v| *v);
+                        assert!(to.is_none() || from.is_none());
+                        if let Some(v) = to.or(from) {
+                            assert_eq!(v, k);
+                        }
+                    }
+                }
+            }));
+        }
+
+        barrier.wait();
+        for k in 0..range {
+            assert_eq!(tree.rename(&k, k + range), Ok(true));
+        }
+        finished.store(true, Release);
+        for t in thread_handles {
+            t.join().unwrap();
+        }
+
+        assert_eq!(tree.len(), range);
+        let guard = Guard::new();
+        assert!(tree
+            .iter(&guard)
+            .map(|(k, v)| (*k, *v))
+            .eq((0..range).map(|k| (k + range, k))));
+    }
+
+    #[cfg_attr(miri, ignore)]
+    #[test]
+    fn rename_put_back() {
+        let num_iter = 65536;
+        let blocker = usize::MAX;
+        let tree: Arc<TreeIndex<usize, usize>> = Arc::new(TreeIndex::default());
+        assert!(tree.insert(0, 7).is_ok());
+
+        let finished = Arc::new(AtomicBool::new(false));
+        let barrier = Arc::new(Barrier::new(3));
+        let mut thread_handles = Vec::with_capacity(2);
+        for first in 0..2 {
+            let barrier = barrier.clone();
+            let finished = finished.clone();
+            let tree = tree.clone();
+            thread_handles.push(thread::spawn(move || {
+                barrier.wait();
+                while !finished.load(Acquire) {
+                    // Occupies both keys while the value is being moved.
+                    let _result = tree.insert(first, blocker);
+                    let _result = tree.insert(1 - first, blocker);
+                    tree.remove_if(&first, |v| *v == blocker);
+                    tree.remove_if(&(1 - first), |v| *v == blocker);
+                }
+            }));
+        }
+
+        barrier.wait();
+        let mut at = 0;
+        for _ in 0..num_iter {
+            match tree.rename(&at, 1 - at) {
+                Ok(moved) => {
+                    assert!(moved);
+                    at = 1 - at;
+                }
+                Err((_, None)) => (),
+                Err((_, Some(mut val))) => {
+                    assert_eq!(val, 7);
+                    while let Err((_, v)) = tree.insert(at, val) {
+                        val = v;
+                        thread::yield_now();
+                    }
+                }
             }
-            for (k, v) in tree.iter(&Guard::new()) {
-                prop_assert_eq!(k, v);
-                prop_assert!(!remove_range.contains(k), "{k}");
+        }
+        finished.store(true, Release);
+        for handle in thread_handles {
+            handle.join().unwrap();
+        }
+
+        assert_eq!(tree.len(), 1);
+        assert_eq!(tree.peek_with(&at, |_, v| *v), Some(7));
+    }
+
+    #[test]
+    fn scan_token() {
+        let range = if cfg!(miri) { 64 } else { 1024 };
+        let tree: TreeIndex<usize, usize> = TreeIndex::default();
+        for k in 0..range {
+            assert!(tree.insert(k, k).is_ok());
+        }
+
+        for page_size in [1, 3, 14, 100, range + 1] {
+            let mut pages = Vec::new();
+            let guard = Guard::new();
+            let mut scan = tree.range(.., &guard);
+            let mut page = scan
+                .by_ref()
+                .take(page_size)
+                .map(|(k, _)| *k)
+                .collect::<Vec<_>>();
+            let mut token = scan.token();
+            drop(guard);
+            while !page.is_empty() {
+                pages.push(page);
+                let Some(t) = token.take() else {
+                    break;
+                };
+                let guard = Guard::new();
+                let mut scan = tree.resume(t, &guard);
+                page = scan.by_ref().take(page_size).map(|(k, _)| *k).collect();
+                token = scan.token();
             }
-            for k in 0_usize..4096_usize {
-                if tree.peek_with(&k, |_, _|()).is_some() {
-                    prop_assert!(!remove_range.contains(&k), "{k}");
-                }
+            assert!(pages.iter().all(|p| p.len() <= page_size));
+            assert_eq!(pages.concat(), (0..range).collect::<Vec<_>>());
+        }
+
+        // A token issued by a bounded range resumes from the first unvisited key, and stops at
+        // the end bound of the range.
+        let guard = Guard::new();
+        let mut bounded = tree.range(4..8, &guard);
+        assert_eq!(bounded.by_ref().take(4).count(), 4);
+        assert!(bounded.next().is_none());
+        assert!(bounded.token().is_none());
+        let bounded = tree.range(4..8, &guard);
+        let token = bounded.token().unwrap();
+        assert_eq!(tree.resume(token, &guard).next(), Some((&4, &4)));
+        let mut beyond = tree.range(range.., &guard);
+        assert!(beyond.next().is_none());
+        assert!(beyond.token().is_none());
+        for page_size in [1, 3, 5] {
+            let mut pages = Vec::new();
+            let mut token = tree.range(4..=16, &guard).token();
+            while let Some(t) = token.take() {
+                let mut scan = tree.resume(t, &guard);
+                pages.push(
+                    scan.by_ref()
+                        .take(page_size)
+                        .map(|(k, _)| *k)
+                        .collect::<Vec<_>>(),
+                );
+                token = scan.token();
             }
-            for k in remove_range.clone() {
-                prop_assert!(tree.insert(k, k).is_ok());
+            assert_eq!(pages.concat(), (4..=16).collect::<Vec<_>>());
+        }
+    }
+
+    #[test]
+    fn leaf_report() {
+        let range = if cfg!(miri) { 64 } else { 1024 };
+        let tree: TreeIndex<usize, usize> = TreeIndex::default();
+        let guard = Guard::new();
+        assert_eq!(tree.leaf_report(&guard).count(), 0);
+
+        for k in 0..range {
+            assert!(tree.insert(k, k).is_ok());
+        }
+
+        // Sequential insertions leave one vacant slot in every leaf except for the last one.
+        let report = tree.leaf_report(&guard).collect::<Vec<_>>();
+        let (last, others) = report.split_last().unwrap();
+        assert!(!others.is_empty());
+        for info in others {
+            assert_eq!(info.len, info.capacity - 1);
+        }
+        assert!(last.len >= 1 && last.len <= last.capacity);
+        assert_eq!(report.iter().map(|info| info.len).sum::<usize>(), range);
+
+        // Leaves are contiguous.
+        assert_eq!(report[0].min_key, Some(&0));
+        assert_eq!(last.max_key, Some(&(range - 1)));
+        for info in &report {
+            assert_eq!(info.max_key.unwrap() - info.min_key.unwrap() + 1, info.len);
+        }
+        for pair in report.windows(2) {
+            assert_eq!(pair[0].max_key.unwrap() + 1, *pair[1].min_key.unwrap());
+        }
+    }
+
+    #[test]
+    fn range_checksum() {
+        let range = if cfg!(miri) { 64 } else { 1024 };
+        let tree: TreeIndex<usize, usize> = TreeIndex::default();
+        let replica: TreeIndex<usize, usize> = TreeIndex::default();
+        assert_eq!(tree.range_checksum(..), replica.range_checksum(..));
+
+        // Checksums are stable across builds and platforms.
+        assert_eq!(tree.range_checksum(..), 0xCBF2_9CE4_8422_2325);
+        assert!(tree.insert(1, 2).is_ok());
+        assert_eq!(tree.range_checksum(..), 0x7717_9803_63C8_E066);
+        assert!(tree.remove(&1));
+
+        let mut keys = (0..range).collect::<Vec<_>>();
+        for k in &keys {
+            assert!(tree.insert(*k, *k).is_ok());
+        }
+        keys.shuffle(&mut thread_rng());
+        for k in &keys {
+            assert!(replica.insert(*k, *k).is_ok());
+        }
+        assert_eq!(tree.range_checksum(..), replica.range_checksum(..));
+        assert_eq!(
+            tree.range_checksum(range / 4..range / 2),
+            replica.range_checksum(range / 4..range / 2)
+        );
+        assert_ne!(
+            tree.range_checksum(range / 4..range / 2),
+            tree.range_checksum(range / 4..=range / 2)
+        );
+
+        let k = range / 3;
+        assert!(replica.remove(&k));
+        assert!(replica.insert(k, k + 1).is_ok());
+        assert_ne!(tree.range_checksum(..), replica.range_checksum(..));
+        assert_ne!(tree.range_checksum(k..=k), replica.range_checksum(k..=k));
+        assert_eq!(
+            tree.range_checksum(k + 1..),
+            replica.range_checksum(k + 1..)
+        );
+        assert_eq!(tree.range_checksum(..k), replica.range_checksum(..k));
+    }
+
+    #[test]
+    fn prefetch_range() {
+        let range = if cfg!(miri) { 64 } else { 1024 };
+        let tree: TreeIndex<usize, usize> = TreeIndex::default();
+        for k in 0..range {
+            assert!(tree.insert(k, k).is_ok());
+        }
+        for k in (0..range).step_by(5) {
+            assert!(tree.remove(&k));
+        }
+
+        let guard = Guard::new();
+        let report = tree.leaf_report(&guard).collect::<Vec<_>>();
+        let num_overlapping = |start: usize, end: usize| {
+            report
+                .iter()
+                .filter(|info| {
+                    info.min_key.map_or(false, |k| *k < end)
+                        && info.max_key.map_or(false, |k| *k >= start)
+                })
+                .count()
+        };
+        assert_eq!(tree.prefetch_range(..), num_overlapping(0, range));
+        assert_eq!(tree.prefetch_range(range..), 0);
+        for start in (0..range).step_by(7) {
+            for len in [0, 1, 13, 14, range / 3] {
+                assert_eq!(
+                    tree.prefetch_range(start..start + len),
+                    num_overlapping(start, start + len),
+                    "{start} {len}"
+                );
             }
-            let mut cnt = 0;
-            for (k, v) in tree.iter(&Guard::new()) {
-                prop_assert_eq!(k, v);
-                if remove_range.contains(k) {
-                    cnt += 1;
+        }
+    }
+
+    #[test]
+    fn reserve_fulfill() {
+        let range = if cfg!(miri) { 64 } else { 1024 };
+        let tree: Arc<TreeIndex<usize, Option<usize>>> = Arc::new(TreeIndex::default());
+        for k in 0..range {
+            assert!(tree.reserve_key(k).is_ok());
+        }
+        assert_eq!(tree.len(), range);
+
+        let barrier = Arc::new(Barrier::new(2));
+        let reader = {
+            let tree = tree.clone();
+            let barrier = barrier.clone();
+            thread::spawn(move || {
+                for k in 0..range {
+                    assert_eq!(tree.peek_with(&k, |_, v| *v), Some(None));
+                }
+                barrier.wait();
+                for k in 0..range {
+                    // A reserved key never disappears while it is being fulfilled.
+                    while let Some(v) = tree.peek_with(&k, |_, v| *v) {
+                        if let Some(v) = v {
+                            assert_eq!(v, k);
+                            break;
+                        }
+                        thread::yield_now();
+                    }
+                    assert!(tree.contains(&k));
                 }
+            })
+        };
+
+        barrier.wait();
+        for k in 0..range {
+            assert!(tree.fulfill(&k, k).is_ok());
+        }
+        reader.join().unwrap();
+        for k in 0..range {
+            assert_eq!(tree.fulfill(&k, k + 1), Err(k + 1));
+            assert_eq!(tree.peek_with(&k, |_, v| *v), Some(Some(k)));
+        }
+        assert_eq!(tree.fulfill(&range, range), Err(range));
+    }
+
+    #[test]
+    fn iter_with_path() {
+        let range = if cfg!(miri) { 64 } else { 4096 };
+        let tree: TreeIndex<usize, usize> = TreeIndex::default();
+        let guard = Guard::new();
+        assert_eq!(tree.iter_with_path(&guard).count(), 0);
+
+        let mut keys = (0..range).collect::<Vec<_>>();
+        keys.shuffle(&mut thread_rng());
+        for k in &keys {
+            assert!(tree.insert(*k, *k).is_ok());
+        }
+        let depth = tree.depth();
+        assert!(depth > 1);
+
+        let mut prev_path: Rc<[usize]> = Rc::from(Vec::new());
+        let mut num_entries = 0;
+        let mut num_paths = 0;
+        for ((k, v, path), (expected_k, expected_v)) in
+            tree.iter_with_path(&guard).zip(tree.iter(&guard))
+        {
+            assert_eq!((k, v), (expected_k, expected_v));
+            assert_eq!(path.len(), depth);
+            assert!(prev_path <= path);
+            if !Rc::ptr_eq(&prev_path, &path) {
+                num_paths += 1;
             }
-            assert_eq!(cnt, range);
+            prev_path = path;
+            num_entries += 1;
+        }
+        assert_eq!(num_entries, range);
+
+        // Entries in the same leaf share the path.
+        assert_eq!(num_paths, tree.leaf_count());
+    }
+
+    #[test]
+    fn insert_buffered() {
+        let range = if cfg!(miri) { 64 } else { 4096 };
+        let tree: TreeIndex<usize, usize> = TreeIndex::default();
+        let mut buffer = tree.insert_buffered(range);
+        for k in (1..range).rev() {
+            buffer.insert(k, k);
+        }
+        assert_eq!(buffer.len(), range - 1);
+        assert!(tree.is_empty());
+        buffer.insert(0, 0);
+        assert!(buffer.is_empty());
+        assert_eq!(tree.len(), range);
+        buffer.insert(range, range);
+        assert!(!tree.contains(&range));
+        drop(buffer);
+        assert!(tree.contains(&range));
+        for (expected, (k, v)) in tree.iter(&Guard::new()).enumerate() {
+            assert_eq!((*k, *v), (expected, expected));
+        }
+
+        let num_threads = if cfg!(miri) { 2 } else { 8 };
+        let tree: Arc<TreeIndex<usize, usize>> = Arc::new(TreeIndex::default());
+        let barrier = Arc::new(Barrier::new(num_threads));
+        let mut thread_handles = Vec::with_capacity(num_threads);
+        for thread_id in 0..num_threads {
+            let tree = tree.clone();
+            let barrier = barrier.clone();
+            thread_handles.push(thread::spawn(move || {
+                barrier.wait();
+                let mut buffer = tree.insert_buffered(16);
+                for k in 0..range {
+                    // Every key is inserted by two threads.
+                    if k % num_threads == thread_id || (k + 1) % num_threads == thread_id {
+                        buffer.insert(k, thread_id);
+                    }
+                }
+                let rejected = buffer.flush();
+                for (k, _) in &rejected {
+                    assert!(tree.contains(k));
+                }
+                rejected.len()
+            }));
+        }
+        let mut num_rejected = 0;
+        for t in thread_handles {
+            num_rejected += t.join().unwrap();
+        }
+        assert_eq!(num_rejected, range);
+        assert_eq!(tree.len(), range);
+        for (expected, (k, _)) in tree.iter(&Guard::new()).enumerate() {
+            assert_eq!(*k, expected);
+        }
+    }
+
+    #[test]
+    fn split_off() {
+        let range = if cfg!(miri) { 64 } else { 4096 };
+        let mut keys = (0..range).collect::<Vec<_>>();
+        keys.shuffle(&mut thread_rng());
+        for at in [0, 1, range / 3, range / 2, range - 1, range, range * 2] {
+            let mut tree: TreeIndex<usize, usize> = TreeIndex::default();
+            for k in &keys {
+                assert!(tree.insert(*k, *k).is_ok());
+            }
+            let upper = tree.split_off(&at);
+            let at = at.min(range);
+            assert_eq!(tree.len(), at);
+            assert_eq!(upper.len(), range - at);
+
+            let guard = Guard::new();
+            for (expected, (k, v)) in tree.iter(&guard).enumerate() {
+                assert_eq!((*k, *v), (expected, expected));
+            }
+            for (expected, (k, v)) in upper.iter(&guard).enumerate() {
+                assert_eq!((*k, *v), (at + expected, at + expected));
+            }
+            for k in 0..range {
+                assert_eq!(tree.contains(&k), k < at);
+                assert_eq!(upper.contains(&k), k >= at);
+            }
+
+            // Both trees remain fully functional.
+            assert!(tree.insert(range, range).is_ok());
+            assert!(upper.remove(&(range - 1)) || at == range);
+            assert_eq!(tree.len(), at + 1);
+        }
+    }
+
+    #[test]
+    fn structurally_eq() {
+        let range = if cfg!(miri) { 64 } else { 1024 };
+        let ascending: TreeIndex<usize, usize> = TreeIndex::default();
+        let descending: TreeIndex<usize, usize> = TreeIndex::default();
+        let guard = Guard::new();
+        assert!(ascending.structurally_eq(&descending, &guard));
+
+        for k in 0..range {
+            assert!(ascending.insert(k, k).is_ok());
+            assert!(descending.insert(range - 1 - k, range - 1 - k).is_ok());
+        }
+        assert!(ascending == descending);
+        assert!(ascending.structurally_eq(&ascending, &guard));
+        assert!(!ascending.structurally_eq(&descending, &guard));
+
+        // Replaying the same history results in the same structure.
+        let replayed: TreeIndex<usize, usize> = TreeIndex::default();
+        for k in 0..range {
+            assert!(replayed.insert(k, k).is_ok());
+        }
+        assert!(ascending.structurally_eq(&replayed, &guard));
+        assert!(replayed.remove(&(range / 2)));
+        assert!(replayed.insert(range / 2, 0).is_ok());
+        assert!(!ascending.structurally_eq(&replayed, &guard));
+    }
+
+    #[test]
+    fn filter_scan() {
+        let range = if cfg!(miri) { 64 } else { 4096 };
+        let tree: TreeIndex<usize, usize> = TreeIndex::default();
+        let mut expected = BTreeMap::new();
+        let mut rng = thread_rng();
+        for k in 0..range {
+            let v = rng.gen_range(0..range);
+            assert!(tree.insert(k, v).is_ok());
+            expected.insert(k, v);
+        }
+
+        let guard = Guard::new();
+        for threshold in [0, range / 4, range / 2, range] {
+            let filtered = tree
+                .filter_scan(|_, v| *v > threshold, &guard)
+                .map(|(k, v)| (*k, *v))
+                .collect::<Vec<_>>();
+            let expected = expected
+                .iter()
+                .filter(|(_, v)| **v > threshold)
+                .map(|(k, v)| (*k, *v))
+                .collect::<Vec<_>>();
+            assert_eq!(filtered, expected);
+        }
+    }
+
+    #[test]
+    fn compare_and_swap_versioned() {
+        let tree: TreeIndex<usize, (u64, usize)> = TreeIndex::default();
+        assert!(tree.insert(1, (0, 10)).is_ok());
+
+        // A reader observes version 0 and value 10, and then the entry goes through A-B-A.
+        let observed = tree.peek_with(&1, |_, v| *v).unwrap();
+        assert_eq!(tree.compare_and_swap_versioned(&1, 0, 20), Ok(1));
+        assert_eq!(tree.compare_and_swap_versioned(&1, 1, 10), Ok(2));
+
+        // Comparing values cannot detect the intermediate change, whereas versions can.
+        assert_eq!(tree.peek_with(&1, |_, (_, v)| *v), Some(observed.1));
+        assert_eq!(
+            tree.compare_and_swap_versioned(&1, observed.0, 30),
+            Err(Some(2))
+        );
+        assert_eq!(tree.peek_with(&1, |_, v| *v), Some((2, 10)));
+        assert_eq!(tree.compare_and_swap_versioned(&2, 0, 30), Err(None));
+
+        let num_threads = if cfg!(miri) { 2 } else { 8 };
+        let num_iters = if cfg!(miri) { 16 } else { 1024 };
+        let tree: Arc<TreeIndex<usize, (u64, usize)>> = Arc::new(TreeIndex::default());
+        assert!(tree.insert(0, (0, 0)).is_ok());
+        let mut thread_handles = Vec::with_capacity(num_threads);
+        for _ in 0..num_threads {
+            let tree = tree.clone();
+            thread_handles.push(thread::spawn(move || {
+                let mut num_success = 0;
+                for _ in 0..num_iters {
+                    let (version, val) = tree.peek_with(&0, |_, v| *v).unwrap();
+                    if tree
+                        .compare_and_swap_versioned(&0, version, val + 1)
+                        .is_ok()
+                    {
+                        num_success += 1;
+                    }
+                }
+                num_success
+            }));
+        }
+        let mut num_success = 0;
+        for t in thread_handles {
+            num_success += t.join().unwrap();
+        }
+        assert_eq!(
+            tree.peek_with(&0, |_, v| *v),
+            Some((num_success as u64, num_success))
+        );
+    }
+
+    #[test]
+    fn for_each_mut() {
+        let range = if cfg!(miri) { 64 } else { 4096 };
+        let num_readers = if cfg!(miri) { 1 } else { 4 };
+        let tree: Arc<TreeIndex<usize, (usize, usize)>> = Arc::new(TreeIndex::default());
+        for k in 0..range {
+            assert!(tree.insert(k, (k, k)).is_ok());
+        }
+
+        let done = Arc::new(AtomicBool::new(false));
+        let mut thread_handles = Vec::with_capacity(num_readers);
+        for _ in 0..num_readers {
+            let tree = tree.clone();
+            let done = done.clone();
+            thread_handles.push(thread::spawn(move || {
+                while !done.load(Acquire) {
+                    let guard = Guard::new();
+                    for (k, v) in tree.iter(&guard) {
+                        assert_eq!(v.0, v.1);
+                        assert!(v.0 == *k || v.0 == *k * 2);
+                    }
+                }
+            }));
+        }
+
+        let mut num_calls = 0;
+        tree.for_each_mut(|k, v| {
+            assert_eq!(*v, (*k, *k));
+            num_calls += 1;
+            *v = (k * 2, k * 2);
+        });
+        done.store(true, Release);
+        for t in thread_handles {
+            t.join().unwrap();
+        }
+
+        // `f` may be invoked again for an entry when its leaf has to be split.
+        assert!(num_calls >= range);
+        assert_eq!(tree.len(), range);
+        for (k, v) in tree.iter(&Guard::new()) {
+            assert_eq!(*v, (k * 2, k * 2));
+        }
+    }
+
+    #[test]
+    fn distinct_values() {
+        let range = if cfg!(miri) { 64 } else { 4096 };
+        let num_values = range / 16;
+        let tree: TreeIndex<usize, usize> = TreeIndex::default();
+        let mut rng = thread_rng();
+        let mut values = Vec::with_capacity(range);
+        for k in 0..range {
+            let v = rng.gen_range(0..num_values);
+            assert!(tree.insert(k, v).is_ok());
+            values.push(v);
+        }
+
+        let mut expected = Vec::new();
+        for v in values {
+            if !expected.contains(&v) {
+                expected.push(v);
+            }
+        }
+        let guard = Guard::new();
+        let distinct = tree.distinct_values(&guard).copied().collect::<Vec<_>>();
+        assert_eq!(distinct, expected);
+    }
+
+    #[test]
+    fn pinned() {
+        static INST_CNT: AtomicUsize = AtomicUsize::new(0);
+        let workload_size = if cfg!(miri) { 64 } else { 1024 };
+        let tree: TreeIndex<usize, R> = TreeIndex::default();
+
+        let mut pinned = tree.pinned(16);
+        for k in 0..workload_size {
+            assert!(pinned.insert(k, R::new(&INST_CNT)).is_ok());
+        }
+        for k in 0..workload_size {
+            assert!(pinned.peek(&k).is_some());
+            assert!(pinned.remove(&k));
+            assert!(pinned.peek(&k).is_none());
+        }
+        tree.clear();
+
+        // Retired entries are reclaimed while the handle keeps being used.
+        while INST_CNT.load(Relaxed) != 0 {
+            assert!(pinned.peek(&0).is_none());
+            Guard::new().accelerate();
+            thread::yield_now();
+        }
+    }
+
+    #[test]
+    fn bfs_nodes() {
+        let tree: TreeIndex<usize, usize> = TreeIndex::default();
+        let guard = Guard::new();
+        assert!(tree.bfs_nodes(&guard).next().is_none());
+
+        let mut k = 0;
+        while tree.depth() < 2 {
+            assert!(tree.insert(k, k).is_ok());
+            k += 1;
+        }
+        let end = k * 2;
+        while k < end {
+            assert!(tree.insert(k, k).is_ok());
+            k += 1;
+        }
+        assert_eq!(tree.depth(), 2);
+
+        let nodes = tree.bfs_nodes(&guard).collect::<Vec<_>>();
+        assert_eq!(nodes[0].level, 0);
+        assert!(!nodes[0].is_leaf_node);
+        assert_eq!(nodes.len(), 1 + nodes[0].num_children);
+        for node in &nodes[1..] {
+            assert_eq!(node.level, 1);
+            assert!(node.is_leaf_node);
+        }
+
+        // Separators are in ascending order across each level.
+        let separators = nodes[1..]
+            .iter()
+            .flat_map(|node| node.separators.iter().copied())
+            .collect::<Vec<_>>();
+        assert!(separators.windows(2).all(|w| w[0] < w[1]));
+        assert_eq!(
+            nodes[1..]
+                .iter()
+                .map(|node| node.num_children)
+                .sum::<usize>(),
+            tree.leaf_report(&guard).count()
+        );
+
+        let range = if cfg!(miri) { 256 } else { 16384 };
+        while k < range {
+            assert!(tree.insert(k, k).is_ok());
+            k += 1;
+        }
+        let depth = tree.depth();
+        let mut prev_level = 0;
+        for node in tree.bfs_nodes(&guard) {
+            assert!(prev_level <= node.level);
+            assert_eq!(node.is_leaf_node, node.level + 1 == depth);
+            prev_level = node.level;
+        }
+        assert_eq!(prev_level + 1, depth);
+    }
+
+    #[test]
+    fn remove_range_drop() {
+        static INST_CNT: AtomicUsize = AtomicUsize::new(0);
+        let workload_size = if cfg!(miri) { 256 } else { 16384 };
+        let tree: TreeIndex<usize, R> = TreeIndex::default();
+        for k in 0..workload_size {
+            assert!(tree.insert(k, R::new(&INST_CNT)).is_ok());
+        }
+
+        // Leaves and nodes fully contained in the range are detached as a whole.
+        let (lower, upper) = (workload_size / 8, workload_size / 8 * 7);
+        tree.remove_range(lower..upper);
+        let num_remaining = workload_size - (upper - lower);
+        assert_eq!(tree.len(), num_remaining);
+
+        // Only the boundary leaves may retain removed entries.
+        let max_retained = num_remaining + 2 * 16;
+        while INST_CNT.load(Relaxed) > max_retained {
+            Guard::new().accelerate();
+            thread::yield_now();
+        }
+
+        let guard = Guard::new();
+        for k in 0..workload_size {
+            assert_eq!(tree.peek(&k, &guard).is_some(), k < lower || k >= upper);
+        }
+    }
+
+    #[test]
+    fn insert_unique() {
+        let num_threads = if cfg!(miri) { 4 } else { 16 };
+        let num_rounds = if cfg!(miri) { 2 } else { 64 };
+        for round in 0..num_rounds {
+            let tree: Arc<TreeIndex<usize, usize>> = Arc::new(TreeIndex::default());
+            let barrier = Arc::new(Barrier::new(num_threads));
+            let mut thread_handles = Vec::with_capacity(num_threads);
+            for thread_id in 0..num_threads {
+                let tree = tree.clone();
+                let barrier = barrier.clone();
+                thread_handles.push(thread::spawn(move || {
+                    barrier.wait();
+                    tree.insert_unique(round, thread_id)
+                }));
+            }
+            let results = thread_handles
+                .into_iter()
+                .map(|t| t.join().unwrap())
+                .collect::<Vec<_>>();
+            let winner = tree.peek_with(&round, |_, v| *v).unwrap();
+            assert_eq!(results.iter().filter(|r| r.is_ok()).count(), 1);
+            assert!(results[winner].is_ok());
+            for result in results {
+                assert!(result.is_ok() || result == Err(winner));
+            }
+        }
+    }
+
+    #[test]
+    fn fold() {
+        let range = if cfg!(miri) { 64 } else { 4096 };
+        let tree: TreeIndex<usize, usize> = TreeIndex::default();
+        assert_eq!(tree.fold(0, |acc, _, v| acc + v), 0);
+
+        let mut keys = (0..range).collect::<Vec<_>>();
+        keys.shuffle(&mut thread_rng());
+        for k in &keys {
+            assert!(tree.insert(*k, k * 2).is_ok());
+        }
+        assert_eq!(tree.fold(0, |acc, _, v| acc + v), range * (range - 1));
+
+        let concatenated = tree.fold(String::new(), |mut acc, k, _| {
+            acc.push_str(&k.to_string());
+            acc
+        });
+        let expected = (0..range).map(|k| k.to_string()).collect::<String>();
+        assert_eq!(concatenated, expected);
+    }
+
+    #[test]
+    fn node_counts() {
+        let tree: TreeIndex<usize, usize> = TreeIndex::default();
+        assert_eq!((tree.leaf_count(), tree.internal_node_count()), (0, 0));
+
+        // The root leaf node has a single unbounded leaf until the leaf is split.
+        let mut k = 0;
+        while tree.leaf_count() == 1 || k == 0 {
+            assert!(tree.insert(k, k).is_ok());
+            assert!(tree.leaf_count() != 1 || tree.internal_node_count() == 1);
+            k += 1;
+        }
+        assert_eq!((tree.leaf_count(), tree.internal_node_count()), (2, 1));
+
+        let range = if cfg!(miri) { 256 } else { 16384 };
+        while k < range {
+            assert!(tree.insert(k, k).is_ok());
+            k += 1;
+        }
+        let guard = Guard::new();
+        let nodes = tree.bfs_nodes(&guard).collect::<Vec<_>>();
+        assert_eq!(tree.internal_node_count(), nodes.len());
+        assert_eq!(
+            tree.leaf_count(),
+            nodes
+                .iter()
+                .filter(|node| node.is_leaf_node)
+                .map(|node| node.num_children)
+                .sum::<usize>()
+        );
+        assert_eq!(tree.leaf_count(), tree.leaf_report(&guard).count());
+
+        // Leaves without entries are not counted.
+        for k in (0..range).filter(|k| k % 64 < 48) {
+            assert!(tree.remove(&k));
+        }
+        assert_eq!(
+            tree.leaf_count(),
+            tree.leaf_report(&guard)
+                .filter(|info| info.len != 0)
+                .count()
+        );
+
+        tree.clear();
+        assert_eq!((tree.leaf_count(), tree.internal_node_count()), (0, 0));
+    }
+
+    #[test]
+    fn iter_until() {
+        let range = if cfg!(miri) { 64 } else { 4096 };
+        let tree: TreeIndex<usize, usize> = TreeIndex::default();
+        let guard = Guard::new();
+        assert_eq!(tree.iter_until(&0, true, &guard).count(), 0);
+
+        let mut keys = (0..range).map(|k| k * 2).collect::<Vec<_>>();
+        keys.shuffle(&mut thread_rng());
+        for k in &keys {
+            assert!(tree.insert(*k, *k).is_ok());
+        }
+
+        for end in [0, 1, range / 2, range, range * 2 - 1, range * 2] {
+            let expected = (0..range).map(|k| k * 2).filter(|k| *k < end);
+            assert!(tree
+                .iter_until(&end, false, &guard)
+                .map(|(k, _)| *k)
+                .eq(expected));
+            let expected = (0..range).map(|k| k * 2).filter(|k| *k <= end);
+            assert!(tree
+                .iter_until(&end, true, &guard)
+                .map(|(k, _)| *k)
+                .eq(expected));
+        }
+
+        // The scan stops at the bound and does not resume.
+        let mut iter = tree.iter_until(&range, true, &guard);
+        assert_eq!(iter.by_ref().count(), range / 2 + 1);
+        assert!(iter.next().is_none());
+    }
+
+    #[test]
+    fn summary() {
+        let range = if cfg!(miri) { 64 } else { 4096 };
+        let tree: TreeIndex<usize, usize> = TreeIndex::default();
+        assert!(tree.summary().is_none());
+
+        let mut rng = thread_rng();
+        for _ in 0..range {
+            let k = rng.gen_range(0..range * 4);
+            let _result = tree.insert(k, k);
+        }
+        tree.remove_range(range..range * 2);
+
+        let guard = Guard::new();
+        let min_key = tree.iter(&guard).next().map(|(k, _)| *k).unwrap();
+        let max_key = tree.iter(&guard).last().map(|(k, _)| *k).unwrap();
+        assert_eq!(tree.summary(), Some((min_key, max_key, tree.len())));
+
+        tree.clear();
+        assert!(tree.summary().is_none());
+    }
+
+    #[test]
+    fn try_for_each() {
+        let range = if cfg!(miri) { 64 } else { 4096 };
+        let tree: TreeIndex<usize, usize> = TreeIndex::default();
+        let mut keys = (0..range).collect::<Vec<_>>();
+        keys.shuffle(&mut thread_rng());
+        for k in &keys {
+            assert!(tree.insert(*k, *k).is_ok());
+        }
+
+        let mut num_visited = 0;
+        assert_eq!(
+            tree.try_for_each(|_, _| {
+                num_visited += 1;
+                Ok::<(), ()>(())
+            }),
+            Ok(())
+        );
+        assert_eq!(num_visited, range);
+
+        let target = range / 3;
+        let mut visited = Vec::new();
+        let result = tree.try_for_each(|k, v| {
+            visited.push(*k);
+            if *k == target {
+                Err(*v)
+            } else {
+                Ok(())
+            }
+        });
+        assert_eq!(result, Err(target));
+        assert_eq!(visited, (0..=target).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn range_by_first() {
+        let range = if cfg!(miri) { 8 } else { 64 };
+        let tree: TreeIndex<(u32, u32), u32> = TreeIndex::default();
+        let guard = Guard::new();
+        assert_eq!(tree.range_by_first(&0, &guard).count(), 0);
+
+        let mut rng = thread_rng();
+        let mut expected = BTreeMap::new();
+        for _ in 0..range * range {
+            let key = (rng.gen_range(0..range), rng.gen_range(0..u32::MAX));
+            if tree.insert(key, key.1).is_ok() {
+                expected.insert(key, key.1);
+            }
+        }
+
+        for a in 0..=range {
+            let scanned = tree
+                .range_by_first(&a, &guard)
+                .map(|(k, v)| (*k, *v))
+                .collect::<Vec<_>>();
+            let expected = expected
+                .iter()
+                .filter(|(k, _)| k.0 == a)
+                .map(|(k, v)| (*k, *v))
+                .collect::<Vec<_>>();
+            assert_eq!(scanned, expected);
+        }
+    }
+
+    #[test]
+    fn key_histogram() {
+        let range = if cfg!(miri) { 256 } else { 16384 };
+        let tree: TreeIndex<u32, u32> = TreeIndex::default();
+        assert!(tree.key_histogram(0, |k| f64::from(*k)).is_empty());
+        assert_eq!(tree.key_histogram(4, |k| f64::from(*k)), vec![0; 4]);
+
+        let mut rng = thread_rng();
+        for _ in 0..range {
+            let _result = tree.insert(rng.gen_range(0..u32::MAX), 0);
+        }
+        let len = tree.len();
+        let histogram = tree.key_histogram(4, |k| f64::from(*k));
+        assert_eq!(histogram.iter().sum::<usize>(), len);
+        for count in histogram {
+            assert!(count > len / 8 && count < len / 2, "{count}");
+        }
+
+        // Squaring the projection skews the distribution towards the first bin.
+        let histogram = tree.key_histogram(4, |k| f64::from(*k).powi(2));
+        assert_eq!(histogram.iter().sum::<usize>(), len);
+        assert!(histogram.windows(2).all(|w| w[0] >= w[1]) || cfg!(miri));
+        assert!(histogram[0] > len / 3);
+
+        tree.clear();
+        assert!(tree.insert(7, 0).is_ok());
+        assert_eq!(tree.key_histogram(3, |k| f64::from(*k)), vec![1, 0, 0]);
+    }
+
+    #[test]
+    fn increment() {
+        let num_threads = if cfg!(miri) { 2 } else { 8 };
+        let num_iter = if cfg!(miri) { 16 } else { 4096 };
+        let tree: Arc<TreeIndex<usize, usize>> = Arc::new(TreeIndex::default());
+        let barrier = Arc::new(Barrier::new(num_threads));
+        let mut thread_handles = Vec::with_capacity(num_threads);
+        for thread_id in 0..num_threads {
+            let tree = tree.clone();
+            let barrier = barrier.clone();
+            thread_handles.push(thread::spawn(move || {
+                barrier.wait();
+                for i in 0..num_iter {
+                    // Neighbouring keys make leaves split while the hot key is incremented.
+                    let _result = tree.insert(thread_id * num_iter + i + 1, 0);
+                    assert!(tree.increment(0, thread_id + 1) > thread_id);
+                }
+            }));
+        }
+        for t in thread_handles {
+            t.join().unwrap();
+        }
+        let expected = (1..=num_threads).sum::<usize>() * num_iter;
+        assert_eq!(tree.peek_with(&0, |_, v| *v), Some(expected));
+        assert_eq!(tree.len(), num_threads * num_iter + 1);
+    }
+
+    #[test]
+    fn merge() {
+        let num_threads = if cfg!(miri) { 2 } else { 8 };
+        let num_iter = if cfg!(miri) { 16 } else { 256 };
+        let tree: Arc<TreeIndex<usize, BTreeSet<usize>>> = Arc::new(TreeIndex::default());
+        let barrier = Arc::new(Barrier::new(num_threads));
+        let mut thread_handles = Vec::with_capacity(num_threads);
+        for thread_id in 0..num_threads {
+            let tree = tree.clone();
+            let barrier = barrier.clone();
+            thread_handles.push(thread::spawn(move || {
+                barrier.wait();
+                for i in 0..num_iter {
+                    let id = thread_id * num_iter + i;
+                    let _result = tree.insert(id + 1, BTreeSet::new());
+                    let merged = tree.merge(0, BTreeSet::from([id]), |a, b| {
+                        a.union(b).copied().collect()
+                    });
+                    assert!(merged.contains(&id));
+                }
+            }));
+        }
+        for t in thread_handles {
+            t.join().unwrap();
+        }
+        let expected = (0..num_threads * num_iter).collect::<BTreeSet<_>>();
+        assert_eq!(tree.peek_with(&0, |_, v| v.clone()), Some(expected));
+    }
+
+    #[test]
+    fn compute_many() {
+        let range = if cfg!(miri) { 64 } else { 4096 };
+        let num_threads = if cfg!(miri) { 2 } else { 4 };
+        let tree: Arc<TreeIndex<usize, usize>> = Arc::new(TreeIndex::default());
+        for k in (0..range).filter(|k| k % 2 == 0) {
+            assert!(tree.insert(k, 10).is_ok());
+        }
+
+        let keys = (0..range).collect::<Vec<_>>();
+        let barrier = Arc::new(Barrier::new(num_threads));
+        let mut thread_handles = Vec::with_capacity(num_threads);
+        for _ in 0..num_threads {
+            let tree = tree.clone();
+            let keys = keys.clone();
+            let barrier = barrier.clone();
+            thread_handles.push(thread::spawn(move || {
+                barrier.wait();
+                tree.compute_many(&keys, |_, v| Some(v.map_or(1, |v| v + 1)));
+            }));
+        }
+        for t in thread_handles {
+            t.join().unwrap();
+        }
+        for k in 0..range {
+            let expected = if k % 2 == 0 {
+                10 + num_threads
+            } else {
+                num_threads
+            };
+            assert_eq!(tree.peek_with(&k, |_, v| *v), Some(expected), "{k}");
+        }
+
+        // Remove every other entry, and leave absent keys absent; `f` is invoked only once for
+        // each removed entry.
+        let mut num_removal_calls = 0;
+        tree.compute_many(&keys, |k, v| {
+            if k % 2 == 0 {
+                v.copied()
+            } else {
+                num_removal_calls += 1;
+                None
+            }
+        });
+        assert_eq!(num_removal_calls, range / 2);
+        assert_eq!(tree.len(), range / 2);
+        for k in 0..range {
+            assert_eq!(tree.contains(&k), k % 2 == 0);
+        }
+        let mut num_calls = 0;
+        tree.compute_many(&[range, range + 1], |_, v| {
+            num_calls += 1;
+            assert!(v.is_none());
+            None
+        });
+        assert_eq!(num_calls, 2);
+        assert_eq!(tree.len(), range / 2);
+    }
+
+    #[test]
+    fn insert_contention() {
+        let range = if cfg!(miri) { 64 } else { 4096 };
+        let num_threads = if cfg!(miri) { 4 } else { 32 };
+        let tree: Arc<TreeIndex<usize, usize>> = Arc::new(TreeIndex::new());
+        let barrier = Arc::new(Barrier::new(num_threads));
+        let mut thread_handles = Vec::with_capacity(num_threads);
+        for thread_id in 0..num_threads {
+            let tree = tree.clone();
+            let barrier = barrier.clone();
+            thread_handles.push(thread::spawn(move || {
+                barrier.wait();
+                // Interleaved keys make every thread split the same leaves.
+                for i in 0..range {
+                    let key = i * num_threads + thread_id;
+                    assert!(tree.insert(key, key).is_ok());
+                    if i % 4 == 0 {
+                        assert!(tree.remove(&key));
+                        assert!(tree.insert(key, key).is_ok());
+                    }
+                }
+            }));
+        }
+        for t in thread_handles {
+            t.join().unwrap();
+        }
+        assert_eq!(tree.len(), num_threads * range);
+        for key in 0..num_threads * range {
+            assert_eq!(tree.peek_with(&key, |_, v| *v), Some(key));
+        }
+    }
+
+    #[test]
+    fn subtree_iter() {
+        let range = if cfg!(miri) { 256 } else { 16384 };
+        let tree: TreeIndex<usize, usize> = TreeIndex::default();
+        let guard = Guard::new();
+        assert_eq!(tree.subtree_iter(None, &guard).count(), 0);
+
+        let mut keys = (0..range).collect::<Vec<_>>();
+        keys.shuffle(&mut thread_rng());
+        for k in keys {
+            assert!(tree.insert(k, k).is_ok());
+        }
+
+        let separators = tree.bfs_nodes(&guard).next().unwrap().separators;
+        assert!(!separators.is_empty());
+        let mut subtree_lens = Vec::new();
+        let mut concatenated = Vec::new();
+        for separator in separators.iter().copied().map(Some).chain([None]) {
+            let len = concatenated.len();
+            concatenated.extend(tree.subtree_iter(separator, &guard).map(|(k, v)| (*k, *v)));
+            subtree_lens.push(concatenated.len() - len);
+            if let Some(separator) = separator {
+                assert!(concatenated.last().map_or(true, |(k, _)| k <= separator));
+            }
+        }
+        assert!(subtree_lens.iter().all(|len| *len != 0));
+        assert_eq!(
+            concatenated,
+            tree.iter(&guard).map(|(k, v)| (*k, *v)).collect::<Vec<_>>()
+        );
+    }
+
+    #[test]
+    fn replace_all() {
+        static INST_CNT: AtomicUsize = AtomicUsize::new(0);
+
+        let range = if cfg!(miri) { 64 } else { 4096 };
+        let tree: Arc<TreeIndex<usize, R>> = Arc::new(TreeIndex::default());
+        tree.replace_all((0..range).map(|k| (k, R::new(&INST_CNT))));
+        assert_eq!(tree.len(), range);
+
+        // A reader that started before the replacement sees the whole old contents.
+        let (started, replaced) = (Arc::new(Barrier::new(2)), Arc::new(Barrier::new(2)));
+        let reader = {
+            let (tree, started, replaced) = (tree.clone(), started.clone(), replaced.clone());
+            thread::spawn(move || {
+                let guard = Guard::new();
+                let mut iter = tree.iter(&guard);
+                assert_eq!(iter.next().map(|(k, _)| *k), Some(0));
+                started.wait();
+                replaced.wait();
+                assert!(iter.map(|(k, _)| *k).eq(1..range));
+            })
+        };
+        started.wait();
+        tree.replace_all((range..range * 2).map(|k| (k, R::new(&INST_CNT))));
+        replaced.wait();
+        reader.join().unwrap();
+
+        let guard = Guard::new();
+        assert!(tree.iter(&guard).map(|(k, _)| *k).eq(range..range * 2));
+        drop(guard);
+
+        // Unsorted and duplicate keys.
+        tree.replace_all([3, 1, 2, 1].into_iter().map(|k| (k, R::new(&INST_CNT))));
+        let guard = Guard::new();
+        assert!(tree.iter(&guard).map(|(k, _)| *k).eq(1..4));
+        drop(guard);
+
+        tree.replace_all(std::iter::empty());
+        assert!(tree.is_empty());
+        while INST_CNT.load(Relaxed) != 0 {
+            Guard::new().accelerate();
+            thread::yield_now();
+        }
+    }
+
+    #[test]
+    fn get_profiled() {
+        let range = if cfg!(miri) { 256 } else { 65536 };
+        let tree: TreeIndex<usize, usize> = TreeIndex::default();
+        let (val, profile) = tree.get_profiled(&0);
+        assert!(val.is_none());
+        assert_eq!(profile, DescentProfile::default());
+
+        let mut keys = (0..range).collect::<Vec<_>>();
+        keys.shuffle(&mut thread_rng());
+        for k in keys {
+            assert!(tree.insert(k, k).is_ok());
+        }
+        let depth = tree.depth();
+        assert!(depth >= 2);
+
+        for k in 0..range {
+            let (val, profile) = tree.get_profiled(&k);
+            assert_eq!(val, Some(k));
+            assert_eq!(profile.levels, depth);
+            assert!(profile.comparisons > depth);
+        }
+
+        // Absent keys descend as deep as existing ones.
+        assert_eq!(tree.get_profiled(&range).1.levels, depth);
+    }
+
+    #[test]
+    fn evict_until() {
+        static INST_CNT: AtomicUsize = AtomicUsize::new(0);
+
+        let range = if cfg!(miri) { 64 } else { 4096 };
+        let tree: TreeIndex<usize, R> = TreeIndex::default();
+        assert_eq!(tree.evict_until(|| false), 0);
+        for k in 0..range {
+            assert!(tree.insert(k, R::new(&INST_CNT)).is_ok());
+        }
+
+        for num_to_evict in [0, 1, 7, range / 2] {
+            let first_key = tree.iter(&Guard::new()).next().map(|(k, _)| *k).unwrap();
+            let mut num_calls = 0;
+            let num_evicted = tree.evict_until(|| {
+                num_calls += 1;
+                num_calls > num_to_evict
+            });
+            assert_eq!(num_evicted, num_to_evict);
+            assert_eq!(num_calls, num_to_evict + 1);
+            assert_eq!(
+                tree.iter(&Guard::new()).next().map(|(k, _)| *k),
+                Some(first_key + num_to_evict)
+            );
+        }
+        let num_remaining = tree.len();
+        assert_eq!(tree.evict_until(|| false), num_remaining);
+        assert!(tree.is_empty());
+        while INST_CNT.load(Relaxed) != 0 {
+            Guard::new().accelerate();
+            thread::yield_now();
+        }
+    }
+
+    #[test]
+    fn count_snapshot() {
+        let range = if cfg!(miri) { 64 } else { 4096 };
+        let tree: TreeIndex<usize, usize> = TreeIndex::default();
+        let guard = Guard::new();
+        let initial = tree.count_snapshot(&guard);
+        assert_eq!(initial.len, 0);
+        assert_eq!(initial.epoch, guard.epoch());
+        drop(guard);
+
+        for k in 0..range {
+            assert!(tree.insert(k, k).is_ok());
+        }
+        let inserted = tree.count_snapshot(&Guard::new());
+        assert_eq!(inserted.len, range);
+        assert_eq!(inserted.delta(&initial), isize::try_from(range).unwrap());
+        assert!(inserted.taken_at >= initial.taken_at);
+
+        tree.remove_range(..range / 4);
+        let removed = tree.count_snapshot(&Guard::new());
+        assert_eq!(
+            removed.delta(&inserted),
+            -isize::try_from(range / 4).unwrap()
+        );
+        assert_eq!(removed.delta(&removed), 0);
+    }
+
+    #[test]
+    fn was_replaced() {
+        let range = if cfg!(miri) { 64 } else { 4096 };
+        let tree: TreeIndex<usize, usize> = TreeIndex::default();
+        for k in 0..range {
+            assert!(tree.insert(k, k).is_ok());
+        }
+
+        // Splits and removals do not count as replacements.
+        let guard = Guard::new();
+        let mut iter = tree.iter(&guard);
+        assert_eq!(iter.next(), Some((&0, &0)));
+        for k in range..range * 2 {
+            assert!(tree.insert(k, k).is_ok());
+        }
+        tree.remove_range(range..);
+        assert!(!iter.was_replaced());
+
+        tree.replace_all((0..range).map(|k| (k, k + 1)));
+        assert!(iter.was_replaced());
+        assert!(iter.by_ref().take(range / 2).all(|(k, v)| k == v));
+        assert!(iter.was_replaced());
+        assert!(!tree.iter(&guard).was_replaced());
+
+        for replace in [
+            TreeIndex::<usize, usize>::clear,
+            TreeIndex::<usize, usize>::rebuild_from_leaves,
+        ] {
+            let iter = tree.iter(&guard);
+            replace(&tree);
+            assert!(iter.was_replaced());
+        }
+
+        // `clear` on an empty tree replaces nothing.
+        tree.clear();
+        let iter = tree.iter(&guard);
+        tree.clear();
+        assert!(!iter.was_replaced());
+    }
+
+    #[test]
+    fn diff() {
+        let range = if cfg!(miri) { 64 } else { 4096 };
+        let old: TreeIndex<usize, usize> = TreeIndex::default();
+        let new: TreeIndex<usize, usize> = TreeIndex::default();
+        let guard = Guard::new();
+        assert_eq!(old.diff(&new, &guard).count(), 0);
+
+        // `old` has `[0, range)`, and `new` has `[range / 2, range * 3 / 2)` with every third value
+        // changed.
+        let mut expected = BTreeMap::new();
+        for k in 0..range {
+            assert!(old.insert(k, k).is_ok());
+            if k < range / 2 {
+                expected.insert(k, "removed");
+            }
+        }
+        for k in range / 2..range * 3 / 2 {
+            let v = if k % 3 == 0 { k + 1 } else { k };
+            assert!(new.insert(k, v).is_ok());
+            if k >= range {
+                expected.insert(k, "added");
+            } else if k % 3 == 0 {
+                expected.insert(k, "changed");
+            }
+        }
+
+        let mut prev_key = None;
+        let mut num_diffs = 0;
+        for diff in old.diff(&new, &guard) {
+            let (k, kind) = match diff {
+                Diff::Added(k, v) => {
+                    assert_eq!(new.peek(k, &guard), Some(v));
+                    assert!(!old.contains(k));
+                    (*k, "added")
+                }
+                Diff::Removed(k, v) => {
+                    assert_eq!(old.peek(k, &guard), Some(v));
+                    assert!(!new.contains(k));
+                    (*k, "removed")
+                }
+                Diff::Changed(k, old_val, new_val) => {
+                    assert_eq!(old.peek(k, &guard), Some(old_val));
+                    assert_eq!(new.peek(k, &guard), Some(new_val));
+                    assert_ne!(old_val, new_val);
+                    (*k, "changed")
+                }
+            };
+            assert!(prev_key.map_or(true, |p| p < k));
+            prev_key.replace(k);
+            assert_eq!(expected.get(&k), Some(&kind));
+            num_diffs += 1;
+        }
+        assert_eq!(num_diffs, expected.len());
+        assert_eq!(new.diff(&old, &guard).count(), expected.len());
+        assert_eq!(old.diff(&old, &guard).count(), 0);
+    }
+
+    #[test]
+    fn multi_range() {
+        let range = if cfg!(miri) { 64 } else { 4096 };
+        let tree: TreeIndex<usize, usize> = TreeIndex::default();
+        let guard = Guard::new();
+        assert_eq!(tree.multi_range([.., ..], &guard).count(), 0);
+        for k in 0..range {
+            assert!(tree.insert(k, k).is_ok());
+        }
+
+        let mut rng = thread_rng();
+        for _ in 0..16 {
+            let num_ranges = rng.gen_range(0..8);
+            let ranges = (0..num_ranges)
+                .map(|_| {
+                    let start = rng.gen_range(0..range + 8);
+                    let start = match rng.gen_range(0..3) {
+                        0 => Bound::Included(start),
+                        1 => Bound::Excluded(start),
+                        _ => Bound::Unbounded,
+                    };
+                    let end = rng.gen_range(0..range + 8);
+                    let end = match rng.gen_range(0..3) {
+                        0 => Bound::Included(end),
+                        1 => Bound::Excluded(end),
+                        _ => Bound::Unbounded,
+                    };
+                    (start, end)
+                })
+                .collect::<Vec<_>>();
+            let expected = (0..range)
+                .filter(|k| ranges.iter().any(|r| r.contains(k)))
+                .collect::<Vec<_>>();
+            let keys = tree
+                .multi_range(ranges.clone(), &guard)
+                .map(|(k, _)| *k)
+                .collect::<Vec<_>>();
+            assert_eq!(keys, expected, "{ranges:?}");
+        }
+
+        let keys = tree
+            .multi_range([8..12, 0..2, 1..4, 10..11], &guard)
+            .map(|(k, _)| *k);
+        assert!(keys.eq([0, 1, 2, 3, 8, 9, 10, 11]));
+    }
+
+    #[test]
+    fn pop_min_max() {
+        let range = if cfg!(miri) { 64 } else { 4096 };
+        let num_threads = if cfg!(miri) { 2 } else { 8 };
+        let tree: Arc<TreeIndex<usize, usize>> = Arc::new(TreeIndex::default());
+        for k in 0..range {
+            assert!(tree.insert(k, k).is_ok());
+        }
+
+        let barrier = Arc::new(Barrier::new(num_threads));
+        let mut thread_handles = Vec::with_capacity(num_threads);
+        for thread_id in 0..num_threads {
+            let tree = tree.clone();
+            let barrier = barrier.clone();
+            thread_handles.push(thread::spawn(move || {
+                let mut popped = Vec::new();
+                barrier.wait();
+                loop {
+                    let entry = if thread_id % 2 == 0 {
+                        tree.pop_min()
+                    } else {
+                        tree.pop_max()
+                    };
+                    let Some((k, v)) = entry else {
+                        break;
+                    };
+                    assert_eq!(k, v);
+                    if let Some(prev) = popped.last() {
+                        // Keys popped by the same thread are monotonic.
+                        assert!(thread_id % 2 == 0 && *prev < k || thread_id % 2 == 1 && *prev > k);
+                    }
+                    popped.push(k);
+                }
+                popped
+            }));
+        }
+        let mut popped = BTreeSet::new();
+        for t in thread_handles {
+            for k in t.join().unwrap() {
+                assert!(popped.insert(k), "{k}");
+            }
+        }
+        assert_eq!(popped.len(), range);
+        assert!(tree.is_empty());
+    }
+
+    #[test]
+    fn resolve_iter() {
+        let range = if cfg!(miri) { 64 } else { 4096 };
+        let tree: TreeIndex<usize, usize> = TreeIndex::default();
+        let storage = Mutex::new(BTreeMap::new());
+        let mut keys = (0..range).collect::<Vec<_>>();
+        keys.shuffle(&mut thread_rng());
+        for (offset, k) in keys.into_iter().enumerate() {
+            assert!(tree.insert(k, offset).is_ok());
+            storage.lock().unwrap().insert(offset, k.to_string());
+        }
+
+        let num_resolved = AtomicUsize::new(0);
+        let guard = Guard::new();
+        let mut iter = tree.resolve_iter(
+            |offset| {
+                num_resolved.fetch_add(1, Relaxed);
+                storage.lock().unwrap().get(offset).cloned()
+            },
+            &guard,
+        );
+        assert_eq!(num_resolved.load(Relaxed), 0);
+        for expected in 0..range / 2 {
+            let (k, resolved) = iter.next().unwrap();
+            assert_eq!(*k, expected);
+            assert_eq!(resolved, Some(expected.to_string()));
+        }
+        assert_eq!(num_resolved.load(Relaxed), range / 2);
+
+        // Resolution is not cached, and sees changes to the storage.
+        let (k, offset) = iter
+            .next()
+            .map(|(k, _)| (*k, tree.peek(k, &guard).copied()))
+            .unwrap();
+        storage.lock().unwrap().remove(&offset.unwrap());
+        assert_eq!(k, range / 2);
+        assert_eq!(iter.count(), range / 2 - 1);
+        assert!(tree
+            .resolve_iter(
+                |offset| storage.lock().unwrap().get(offset).cloned(),
+                &guard
+            )
+            .all(|(k, resolved)| resolved.map_or(*k == range / 2, |r| r == k.to_string())));
+    }
+
+    #[test]
+    fn scan_after() {
+        let range = if cfg!(miri) { 64 } else { 4096 };
+        let tree: TreeIndex<usize, usize> = TreeIndex::default();
+        let guard = Guard::new();
+        assert!(tree.scan_after(&0, &guard).next().is_none());
+        for k in (0..range).map(|k| k * 2) {
+            assert!(tree.insert(k, k).is_ok());
+        }
+
+        // Existing and absent keys.
+        for k in 0..range * 2 {
+            let successor = (k + 1) / 2 * 2 + if k % 2 == 0 { 2 } else { 0 };
+            let mut iter = tree.scan_after(&k, &guard);
+            assert_eq!(
+                iter.next().map(|(k, _)| *k),
+                (successor < range * 2).then_some(successor),
+                "{k}"
+            );
+        }
+
+        // Paginate through all the entries.
+        let mut last_key = None;
+        let mut num_pages = 0;
+        let mut visited = Vec::new();
+        loop {
+            let page = match last_key {
+                None => tree
+                    .iter(&guard)
+                    .take(7)
+                    .map(|(k, _)| *k)
+                    .collect::<Vec<_>>(),
+                Some(k) => tree
+                    .scan_after(&k, &guard)
+                    .take(7)
+                    .map(|(k, _)| *k)
+                    .collect(),
+            };
+            let Some(k) = page.last() else {
+                break;
+            };
+            last_key.replace(*k);
+            visited.extend(page);
+            num_pages += 1;
+        }
+        assert_eq!(num_pages, (range + 6) / 7);
+        assert!(visited.into_iter().eq((0..range).map(|k| k * 2)));
+    }
+
+    #[cfg_attr(miri, ignore)]
+    #[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+    async fn for_each_async() {
+        let range = 4096;
+        let tree: Arc<TreeIndex<usize, usize>> = Arc::new(TreeIndex::default());
+        for k in 0..range {
+            assert!(tree.insert(k, k * 2).is_ok());
+        }
+
+        let (sender, mut receiver) = tokio::sync::mpsc::channel(16);
+        let tree_clone = tree.clone();
+        let producer = tokio::spawn(async move {
+            tree_clone
+                .for_each_async(|k, v| {
+                    let sender = sender.clone();
+                    async move { sender.send((k, v)).await.is_ok() }
+                })
+                .await
+        });
+        let mut entries = Vec::new();
+        while let Some(entry) = receiver.recv().await {
+            entries.push(entry);
+        }
+        assert!(producer.await.unwrap());
+        assert!(entries.into_iter().eq((0..range).map(|k| (k, k * 2))));
+
+        // Dropping the receiver stops the scan.
+        let (sender, mut receiver) = tokio::sync::mpsc::channel(1);
+        let tree_clone = tree.clone();
+        let producer = tokio::spawn(async move {
+            tree_clone
+                .for_each_async(|k, v| {
+                    let sender = sender.clone();
+                    async move { sender.send((k, v)).await.is_ok() }
+                })
+                .await
+        });
+        assert_eq!(receiver.recv().await, Some((0, 0)));
+        drop(receiver);
+        assert!(!producer.await.unwrap());
+    }
+
+    #[test]
+    fn first_gap() {
+        let tree: TreeIndex<u64, u64> = TreeIndex::default();
+        assert_eq!(tree.first_gap(0), 0);
+        assert_eq!(tree.first_gap(7), 7);
+        for k in (0..100).filter(|k| *k != 42) {
+            assert!(tree.insert(k, k).is_ok());
+        }
+        assert_eq!(tree.first_gap(0), 42);
+        assert_eq!(tree.first_gap(42), 42);
+        assert_eq!(tree.first_gap(43), 100);
+        assert_eq!(tree.first_gap(50), 100);
+        assert_eq!(tree.first_gap(100), 100);
+        assert_eq!(tree.first_gap(1000), 1000);
+        assert!(tree.insert(42, 42).is_ok());
+        assert_eq!(tree.first_gap(0), 100);
+    }
+
+    #[test]
+    fn claim_first_gap() {
+        let num_threads = if cfg!(miri) { 2 } else { 8 };
+        let num_iter = if cfg!(miri) { 16 } else { 256 };
+        let tree: Arc<TreeIndex<usize, usize>> = Arc::new(TreeIndex::default());
+        let barrier = Arc::new(Barrier::new(num_threads));
+        let mut thread_handles = Vec::with_capacity(num_threads);
+        for thread_id in 0..num_threads {
+            let tree = tree.clone();
+            let barrier = barrier.clone();
+            thread_handles.push(thread::spawn(move || {
+                barrier.wait();
+                (0..num_iter)
+                    .map(|_| {
+                        let key = tree.claim_first_gap(0, thread_id);
+                        assert_eq!(tree.peek_with(&key, |_, v| *v), Some(thread_id));
+                        key
+                    })
+                    .collect::<Vec<_>>()
+            }));
+        }
+        let mut claimed = BTreeSet::new();
+        for t in thread_handles {
+            for key in t.join().unwrap() {
+                assert!(claimed.insert(key), "{key}");
+            }
+        }
+        assert!(claimed.into_iter().eq(0..num_threads * num_iter));
+        assert_eq!(tree.first_gap(0), num_threads * num_iter);
+    }
+
+    #[test]
+    fn windows3() {
+        let range = if cfg!(miri) { 64 } else { 1024 };
+        let tree: TreeIndex<usize, usize> = TreeIndex::default();
+        let guard = Guard::new();
+        assert!(tree.windows3(&guard).next().is_none());
+        assert!(tree.insert(1, 2).is_ok());
+        assert!(tree
+            .windows3(&guard)
+            .eq([(None, (&1, &2), None)].into_iter()));
+
+        for k in 2..range {
+            assert!(tree.insert(k, k * 2).is_ok());
+        }
+        let mut num_windows = 0;
+        for (prev, (k, v), next) in tree.windows3(&guard) {
+            assert_eq!(*v, *k * 2);
+            assert_eq!(prev.map(|(k, _)| *k), (*k > 1).then(|| *k - 1));
+            assert_eq!(next.map(|(k, _)| *k), (*k < range - 1).then(|| *k + 1));
+            num_windows += 1;
+        }
+        assert_eq!(num_windows, range - 1);
+    }
+
+    #[test]
+    fn insert_bounded() {
+        for max_depth in 1..=2 {
+            let tree: TreeIndex<usize, usize> = TreeIndex::default();
+            assert_eq!(
+                tree.insert_bounded(0, 0, 0),
+                Err(InsertError::Overflow(0, 0))
+            );
+            let mut num_inserted = 0;
+            while tree
+                .insert_bounded(num_inserted, num_inserted, max_depth)
+                .is_ok()
+            {
+                num_inserted += 1;
+            }
+            assert_eq!(tree.depth(), max_depth);
+            assert_eq!(
+                tree.insert_bounded(num_inserted, 0, max_depth),
+                Err(InsertError::Overflow(num_inserted, 0))
+            );
+            assert_eq!(
+                tree.insert_bounded(0, 1, max_depth),
+                Err(InsertError::Duplicate(0, 1))
+            );
+
+            // Rejected inserts leave the tree intact.
+            for k in num_inserted..num_inserted * 2 {
+                assert!(tree.insert_bounded(k, k, max_depth).is_err());
+            }
+            assert_eq!(tree.depth(), max_depth);
+            assert_eq!(tree.len(), num_inserted);
+            assert!(tree
+                .iter(&Guard::new())
+                .map(|(k, v)| (*k, *v))
+                .eq((0..num_inserted).map(|k| (k, k))));
+
+            // The tree can still grow without the limit.
+            assert!(tree.insert(num_inserted, num_inserted).is_ok());
+            assert_eq!(tree.depth(), max_depth + 1);
+            assert!(tree.remove(&num_inserted));
+            assert!(tree.insert_bounded(num_inserted, 0, max_depth).is_ok());
+        }
+    }
+
+    #[test]
+    fn stale_entries() {
+        let range = if cfg!(miri) { 64 } else { 1024 };
+        let tree: TreeIndex<usize, (Instant, usize)> = TreeIndex::default();
+        let guard = Guard::new();
+        assert!(tree.stale_entries(Instant::now(), &guard).next().is_none());
+
+        let first_batch = Instant::now();
+        for k in (0..range).filter(|k| k % 3 == 0) {
+            assert!(tree.insert(k, (first_batch, k)).is_ok());
+        }
+        let second_batch = first_batch + Duration::from_secs(1);
+        for k in (0..range).filter(|k| k % 3 != 0) {
+            assert!(tree.insert(k, (second_batch, k)).is_ok());
+        }
+
+        assert!(tree.stale_entries(first_batch, &guard).next().is_none());
+        assert!(tree
+            .stale_entries(second_batch, &guard)
+            .map(|(k, v)| (*k, *v))
+            .eq((0..range).filter(|k| k % 3 == 0).map(|k| (k, k))));
+        assert_eq!(
+            tree.stale_entries(second_batch + Duration::from_nanos(1), &guard)
+                .count(),
+            range
+        );
+    }
+
+    #[test]
+    fn rotate() {
+        let range = if cfg!(miri) { 64 } else { 4096 };
+        for capacity in [0, 1, 7, 64] {
+            let tree: TreeIndex<usize, usize> = TreeIndex::default();
+            for k in 0..range {
+                let evicted = tree.rotate(k, k * 2, capacity);
+                if k >= capacity {
+                    assert_eq!(evicted, Some((k - capacity, (k - capacity) * 2)));
+                } else {
+                    assert!(evicted.is_none());
+                }
+            }
+            if capacity > 0 {
+                assert!(tree.rotate(range - 1, 0, capacity).is_none());
+                assert_eq!(
+                    tree.peek_with(&(range - 1), |_, v| *v),
+                    Some((range - 1) * 2)
+                );
+            }
+            assert!(tree
+                .iter(&Guard::new())
+                .map(|(k, _)| *k)
+                .eq(range - capacity..range));
+
+            // The number of entries is counted again once the root is replaced.
+            tree.clear();
+            for k in 0..capacity {
+                assert!(tree.insert(k, k).is_ok());
+            }
+            let expected = if capacity > 0 { 0 } else { range };
+            assert_eq!(
+                tree.rotate(range, range, capacity).map(|(k, _)| k),
+                Some(expected)
+            );
+            assert_eq!(tree.len(), capacity);
+        }
+    }
+
+    #[test]
+    fn scan_into() {
+        let range = if cfg!(miri) { 64 } else { 4096 };
+        let tree: TreeIndex<usize, usize> = TreeIndex::default();
+        let mut buffer = Vec::new();
+        assert!(tree.scan_into(&mut buffer, None, 16).is_none());
+        assert!(buffer.is_empty());
+        for k in 0..range {
+            assert!(tree.insert(k, k * 2).is_ok());
+        }
+        assert_eq!(tree.scan_into(&mut buffer, Some(&11), 0), Some(11));
+        assert!(buffer.is_empty());
+
+        for max in [1, 7, 64, range, range + 1] {
+            let mut entries = Vec::new();
+            let mut next = None;
+            loop {
+                buffer.clear();
+                next = tree.scan_into(&mut buffer, next.as_ref(), max);
+                assert!(buffer.len() == max || next.is_none());
+                entries.extend_from_slice(&buffer);
+                if next.is_none() {
+                    break;
+                }
+            }
+            assert!(entries.into_iter().eq((0..range).map(|k| (k, k * 2))));
+        }
+    }
+
+    #[test]
+    fn take_all() {
+        static INST_CNT: AtomicUsize = AtomicUsize::new(0);
+        let range = if cfg!(miri) { 64 } else { 4096 };
+        let tree: TreeIndex<usize, R> = TreeIndex::default();
+        assert!(tree.take_all().next().is_none());
+        for k in 0..range {
+            assert!(tree.insert(k, R::new(&INST_CNT)).is_ok());
+        }
+
+        let guard = Guard::new();
+        let mut iter = tree.iter(&guard);
+        assert_eq!(iter.next().map(|(k, _)| *k), Some(0));
+        let entries = tree.take_all().collect::<Vec<_>>();
+        assert!(iter.was_replaced());
+        assert!(entries.iter().map(|(k, _)| *k).eq(0..range));
+        assert!(tree.is_empty());
+        assert_eq!(tree.depth(), 0);
+
+        // Readers of the old tree still observe the old entries.
+        assert_eq!(iter.count(), range - 1);
+        drop(guard);
+
+        assert!(tree.insert(range, R::new(&INST_CNT)).is_ok());
+        assert_eq!(tree.take_all().count(), 1);
+
+        // Entries not taken are dropped along with the detached tree.
+        for k in 0..range {
+            assert!(tree.insert(k, R::new(&INST_CNT)).is_ok());
+        }
+        let mut partial = tree.take_all();
+        assert_eq!(partial.next().map(|(k, _)| k), Some(0));
+        drop(partial);
+        drop(entries);
+        drop(tree);
+
+        while INST_CNT.load(Relaxed) != 0 {
+            Guard::new().accelerate();
+            thread::yield_now();
+        }
+    }
+
+    #[test]
+    fn map_values() {
+        let range = if cfg!(miri) { 64 } else { 4096 };
+        let tree: TreeIndex<u32, u32> = TreeIndex::default();
+        assert!(tree.map_values(|_, v| v.to_string()).is_empty());
+        for k in 0..range {
+            assert!(tree.insert(k, k * 2).is_ok());
+        }
+
+        let mapped: TreeIndex<u32, String> = tree.map_values(|k, v| format!("{k}-{v}"));
+        assert_eq!(mapped.len(), tree.len());
+        assert!(mapped
+            .iter(&Guard::new())
+            .map(|(k, v)| (*k, v.clone()))
+            .eq((0..range).map(|k| (k, format!("{k}-{}", k * 2)))));
+        for k in 0..range {
+            assert_eq!(
+                mapped.peek_with(&k, |_, v| v.clone()),
+                Some(format!("{k}-{}", k * 2))
+            );
+        }
+        assert!(mapped.insert(range, String::new()).is_ok());
+        assert!(!tree.contains(&range));
+    }
+
+    #[test]
+    fn for_each_until_deadline() {
+        let range = if cfg!(miri) { 64 } else { 4096 };
+        let tree: TreeIndex<usize, usize> = TreeIndex::default();
+        assert_eq!(
+            tree.for_each_until_deadline(|_, _| unreachable!(), Instant::now()),
+            ScanStatus::Completed
+        );
+        for k in 0..range {
+            assert!(tree.insert(k, k * 2).is_ok());
+        }
+
+        let deadline = Instant::now() + Duration::from_secs(600);
+        let mut entries = Vec::new();
+        assert_eq!(
+            tree.for_each_until_deadline(|k, v| entries.push((*k, *v)), deadline),
+            ScanStatus::Completed
+        );
+        assert!(entries.into_iter().eq((0..range).map(|k| (k, k * 2))));
+
+        // An expired deadline stops the scan after the first leaf.
+        let mut keys = Vec::new();
+        let status = tree.for_each_until_deadline(|k, _| keys.push(*k), Instant::now());
+        let ScanStatus::TimedOut(last_key) = status else {
+            unreachable!("{status:?}");
+        };
+        assert_eq!(keys.last(), Some(&last_key));
+        assert!(!keys.is_empty() && keys.len() < range);
+        keys.extend(tree.scan_after(&last_key, &Guard::new()).map(|(k, _)| *k));
+        assert!(keys.into_iter().eq(0..range));
+    }
+
+    #[test]
+    fn missing_keys() {
+        let range = if cfg!(miri) { 64 } else { 4096 };
+        let tree: TreeIndex<usize, usize> = TreeIndex::default();
+        let expected = (0..range).collect::<Vec<_>>();
+        assert_eq!(tree.missing_keys(&expected), expected);
+        for k in (0..range).filter(|k| k % 3 != 0) {
+            assert!(tree.insert(k, k).is_ok());
+        }
+
+        assert!(tree.missing_keys(&[]).is_empty());
+        assert_eq!(
+            tree.missing_keys(&expected),
+            (0..range).filter(|k| k % 3 == 0).collect::<Vec<_>>()
+        );
+        let expected = (0..range * 2).step_by(2).collect::<Vec<_>>();
+        assert_eq!(
+            tree.missing_keys(&expected),
+            expected
+                .iter()
+                .copied()
+                .filter(|k| k % 3 == 0 || *k >= range)
+                .collect::<Vec<_>>()
+        );
+        assert_eq!(tree.missing_keys(&[1, 1, 3, 3]), vec![3, 3]);
+    }
+
+    #[test]
+    fn atomic_rebuild() {
+        static INST_CNT: AtomicUsize = AtomicUsize::new(0);
+        let range = if cfg!(miri) { 64 } else { 4096 };
+        let tree: TreeIndex<usize, R> = TreeIndex::default();
+        for k in 0..range {
+            assert!(tree.insert(k, R::new(&INST_CNT)).is_ok());
+        }
+
+        let guard = Guard::new();
+        let iter = tree.iter(&guard);
+        let result = catch_unwind(AssertUnwindSafe(|| {
+            tree.atomic_rebuild(|rebuilt| {
+                for (k, v) in tree.iter(&Guard::new()) {
+                    assert!(*k != range / 2, "panic in the middle");
+                    assert!(rebuilt.insert_at_tail(*k, v.clone()).is_ok());
+                }
+                Ok::<(), ()>(())
+            })
+        }));
+        assert!(result.is_err());
+        assert!(!iter.was_replaced());
+        assert!(tree.iter(&guard).map(|(k, _)| *k).eq(0..range));
+
+        assert_eq!(
+            tree.atomic_rebuild(|rebuilt| {
+                assert!(rebuilt.insert(range, R::new(&INST_CNT)).is_ok());
+                Err(range)
+            }),
+            Err(range)
+        );
+        assert!(!iter.was_replaced());
+        assert!(!tree.contains(&range));
+
+        assert!(tree
+            .atomic_rebuild(|rebuilt| {
+                for (k, v) in tree.iter(&Guard::new()).filter(|(k, _)| **k % 2 == 0) {
+                    assert!(rebuilt.insert_at_tail(*k, v.clone()).is_ok());
+                }
+                Ok::<(), ()>(())
+            })
+            .is_ok());
+        assert!(iter.was_replaced());
+        assert!(tree
+            .iter(&guard)
+            .map(|(k, _)| *k)
+            .eq((0..range).filter(|k| k % 2 == 0)));
+        drop(guard);
+        drop(tree);
+
+        while INST_CNT.load(Relaxed) != 0 {
+            Guard::new().accelerate();
+            thread::yield_now();
+        }
+    }
+
+    #[cfg_attr(miri, ignore)]
+    #[test]
+    fn rebuild_concurrent_writers() {
+        let num_threads = 4;
+        let workload_size = 4096;
+        let tree: Arc<TreeIndex<usize, usize>> = Arc::new(TreeIndex::default());
+        let barrier = Arc::new(Barrier::new(num_threads + 1));
+        let mut thread_handles = Vec::with_capacity(num_threads);
+        for task_id in 0..num_threads {
+            let barrier_clone = barrier.clone();
+            let tree_clone = tree.clone();
+            thread_handles.push(thread::spawn(move || {
+                barrier_clone.wait();
+                let range = task_id * workload_size..(task_id + 1) * workload_size;
+                for k in range.clone() {
+                    assert!(tree_clone.insert(k, k).is_ok());
+                }
+                for k in range.filter(|k| k % 2 == 0) {
+                    // Fails if the insertion above was lost.
+                    assert!(tree_clone.remove(&k));
+                }
+            }));
+        }
+
+        barrier.wait();
+        let mut round = 0;
+        while !thread_handles.iter().all(thread::JoinHandle::is_finished) {
+            match round % 3 {
+                0 => tree.rebuild_from_leaves(),
+                1 => {
+                    tree.ensure_balanced();
+                }
+                _ => assert!(tree
+                    .atomic_rebuild(|rebuilt| {
+                        for (k, v) in tree.iter(&Guard::new()) {
+                            assert!(rebuilt.insert_at_tail(*k, *v).is_ok());
+                        }
+                        Ok::<(), ()>(())
+                    })
+                    .is_ok()),
+            }
+            round += 1;
+        }
+        for handle in thread_handles {
+            handle.join().unwrap();
+        }
+
+        assert!(tree
+            .iter(&Guard::new())
+            .map(|(k, v)| (*k, *v))
+            .eq((0..num_threads * workload_size)
+                .filter(|k| k % 2 == 1)
+                .map(|k| (k, k))));
+    }
+
+    #[test]
+    fn from_iter() {
+        let range = if cfg!(miri) { 64 } else { 4096 };
+        let tree: TreeIndex<usize, usize> = std::iter::empty().collect();
+        assert!(tree.is_empty());
+
+        let mut entries = (0..range).map(|k| (k, k)).collect::<Vec<_>>();
+        entries.extend((0..range).step_by(3).map(|k| (k, k * 2)));
+        entries.shuffle(&mut thread_rng());
+        let duplicates =
+            entries
+                .iter()
+                .filter(|(k, _)| k % 3 == 0)
+                .fold(BTreeMap::new(), |mut last, (k, v)| {
+                    last.insert(*k, *v);
+                    last
+                });
+        let tree: TreeIndex<usize, usize> = entries.into_iter().collect();
+        assert_eq!(tree.len(), range);
+        for k in 0..range {
+            let expected = duplicates.get(&k).copied().unwrap_or(k);
+            assert_eq!(tree.peek_with(&k, |_, v| *v), Some(expected));
+        }
+
+        // The bulk-loaded tree is no deeper than one built by sequential insertions.
+        let inserted = TreeIndex::default();
+        for k in 0..range {
+            assert!(inserted.insert(k, k).is_ok());
+        }
+        assert!(tree.depth() <= inserted.depth());
+        assert!(tree.insert(range, range).is_ok());
+    }
+
+    #[test]
+    fn extend() {
+        let range = if cfg!(miri) { 64 } else { 4096 };
+        let mut tree: TreeIndex<usize, usize> = TreeIndex::default();
+        tree.extend(std::iter::empty());
+        assert!(tree.is_empty());
+        for k in (0..range).step_by(2) {
+            assert!(tree.insert(k, k).is_ok());
+        }
+        let depth = tree.depth();
+
+        // Leaves are split in the middle of the batch.
+        tree.extend((0..range * 2).map(|k| (k, k * 2)));
+        assert!(tree.depth() >= depth);
+        assert_eq!(tree.len(), range * 2);
+        for k in 0..range * 2 {
+            let expected = if k < range && k % 2 == 0 { k } else { k * 2 };
+            assert_eq!(tree.peek_with(&k, |_, v| *v), Some(expected));
+        }
+    }
+
+    #[test]
+    fn into_iter() {
+        static INST_CNT: AtomicUsize = AtomicUsize::new(0);
+        let range = if cfg!(miri) { 64 } else { 4096 };
+        let tree: TreeIndex<usize, R> = TreeIndex::default();
+        assert!(tree.into_iter().next().is_none());
+
+        let mut keys = (0..range).collect::<Vec<_>>();
+        keys.shuffle(&mut thread_rng());
+        let tree: TreeIndex<usize, R> = TreeIndex::default();
+        for k in keys {
+            assert!(tree.insert(k, R::new(&INST_CNT)).is_ok());
+        }
+        let entries = tree.into_iter().collect::<Vec<_>>();
+        assert!(entries.iter().map(|(k, _)| *k).eq(0..range));
+        drop(entries);
+
+        while INST_CNT.load(Relaxed) != 0 {
+            Guard::new().accelerate();
+            thread::yield_now();
+        }
+    }
+
+    #[test]
+    fn take_all_leafwise() {
+        static NUM_CLONES: AtomicUsize = AtomicUsize::new(0);
+        #[derive(Debug, Eq, PartialEq)]
+        struct C(usize);
+        impl Clone for C {
+            fn clone(&self) -> Self {
+                NUM_CLONES.fetch_add(1, Relaxed);
+                C(self.0)
+            }
+        }
+        let range = if cfg!(miri) { 64 } else { 4096 };
+        let tree: TreeIndex<usize, C> = TreeIndex::default();
+        for k in 0..range {
+            assert!(tree.insert(k, C(k)).is_ok());
+        }
+        NUM_CLONES.store(0, Relaxed);
+
+        // References to the entries of the old tree stay valid.
+        let guard = Guard::new();
+        let val = tree.peek(&1, &guard).unwrap();
+
+        // Only the first leaf is cloned when the first entry is taken.
+        let mut entries = tree.take_all();
+        assert_eq!(entries.next(), Some((0, C(0))));
+        let num_clones = NUM_CLONES.load(Relaxed);
+        assert!(num_clones > 0 && num_clones < range / 2, "{num_clones}");
+        assert!(entries.by_ref().eq((1..range).map(|k| (k, C(k)))));
+        assert_eq!(NUM_CLONES.load(Relaxed), range);
+        assert!(entries.next().is_none());
+        drop(entries);
+        assert_eq!(*val, C(1));
+    }
+
+    #[cfg(feature = "stream")]
+    #[cfg_attr(miri, ignore)]
+    #[tokio::test]
+    async fn stream() {
+        use futures::StreamExt;
+
+        let range = 4096;
+        let tree: TreeIndex<usize, usize> = TreeIndex::default();
+        assert!(tree.stream().next().await.is_none());
+
+        let mut keys = (0..range).collect::<Vec<_>>();
+        keys.shuffle(&mut thread_rng());
+        for k in keys {
+            assert!(tree.insert(k, k).is_ok());
+        }
+        let entries = tree.stream().collect::<Vec<_>>().await;
+        assert!(entries.into_iter().eq((0..range).map(|k| (k, k))));
+
+        // Entries removed in the meantime are not yielded unless they were in the first leaf.
+        let num_first_leaf_entries = {
+            let guard = Guard::new();
+            let mut iter = tree.iter_with_path(&guard);
+            let (_, _, first_path) = iter.next().unwrap();
+            1 + iter.take_while(|(_, _, path)| *path == first_path).count()
+        };
+        let mut stream = tree.stream();
+        assert_eq!(stream.next().await, Some((0, 0)));
+        for k in 1..range / 2 {
+            assert!(tree.remove(&k));
+        }
+        let entries = stream.collect::<Vec<_>>().await;
+        assert!(entries.into_iter().eq((1..num_first_leaf_entries)
+            .chain(range / 2..range)
+            .map(|k| (k, k))));
+    }
+
+    #[test]
+    fn split_storm() {
+        let (num_threads, num_keys_per_thread) = if cfg!(miri) { (2, 64) } else { (16, 4096) };
+        assert!(stress_insert_contended(num_threads, num_keys_per_thread));
+    }
+
+    /// Makes threads insert keys in overlapping ranges, and checks if every distinct key is found
+    /// afterwards.
+    ///
+    /// Each thread inserts a range of keys that half overlaps with the ranges of its neighbours in
+    /// an order that differs from theirs, so that threads compete for splitting the same leaves.
+    fn stress_insert_contended(num_threads: usize, num_keys_per_thread: usize) -> bool {
+        let tree: Arc<TreeIndex<usize, usize>> = Arc::new(TreeIndex::default());
+        let barrier = Arc::new(Barrier::new(num_threads));
+        let mut thread_handles = Vec::with_capacity(num_threads);
+        for thread_id in 0..num_threads {
+            let tree = tree.clone();
+            let barrier = barrier.clone();
+            thread_handles.push(thread::spawn(move || {
+                let first_key = thread_id * num_keys_per_thread / 2;
+                let mut keys = (first_key..first_key + num_keys_per_thread).collect::<Vec<_>>();
+                if thread_id % 2 == 1 {
+                    keys.reverse();
+                }
+                barrier.wait();
+                for k in keys {
+                    let _result = tree.insert(k, k);
+                }
+            }));
+        }
+        for t in thread_handles {
+            t.join().unwrap();
+        }
+        let num_distinct_keys = (num_threads + 1) * num_keys_per_thread / 2;
+        let guard = Guard::new();
+        tree.iter(&guard).count() == num_distinct_keys
+            && tree.iter(&guard).map(|(k, _)| *k).eq(0..num_distinct_keys)
+    }
+
+    /// An operation recorded in an [`OpLog`].
+    #[derive(Clone, Copy, Debug)]
+    enum Op {
+        Insert(usize, usize),
+        Remove(usize),
+    }
+
+    /// An append-only log of operations on a [`TreeIndex`] ordered by logical sequence numbers.
+    #[derive(Default)]
+    struct OpLog {
+        seq: AtomicUsize,
+        log: Mutex<Vec<(usize, Op, bool)>>,
+    }
+
+    impl OpLog {
+        fn apply(&self, tree: &TreeIndex<usize, usize>, op: Op) {
+            let result = match op {
+                Op::Insert(k, v) => tree.insert(k, v).is_ok(),
+                Op::Remove(k) => tree.remove(&k),
+            };
+            let seq = self.seq.fetch_add(1, Relaxed);
+            self.log.lock().unwrap().push((seq, op, result));
+        }
+
+        /// Replays the log, and checks if it is consistent with the final state of the tree.
+        ///
+        /// Each key must only be modified by a single thread.
+        fn reconcile(&self, tree: &TreeIndex<usize, usize>) -> Result<(), String> {
+            let mut log = self.log.lock().unwrap().clone();
+            log.sort_unstable_by_key(|(seq, _, _)| *seq);
+            let mut expected = BTreeMap::new();
+            for (seq, op, result) in log {
+                let expected_result = match op {
+                    Op::Insert(k, v) => {
+                        if let std::collections::btree_map::Entry::Vacant(e) = expected.entry(k) {
+                            e.insert(v);
+                            true
+                        } else {
+                            false
+                        }
+                    }
+                    Op::Remove(k) => expected.remove(&k).is_some(),
+                };
+                if result != expected_result {
+                    return Err(format!("{seq}: {op:?} returned {result}"));
+                }
+            }
+            let guard = Guard::new();
+            let actual = tree.iter(&guard).map(|(k, v)| (*k, *v)).collect::<Vec<_>>();
+            if !actual.into_iter().eq(expected) {
+                return Err("the final state does not match the log".to_string());
+            }
+            Ok(())
+        }
+    }
+
+    proptest! {
+        #[cfg_attr(miri, ignore)]
+        #[test]
+        fn prop_remove_range(lower in 0_usize..4096_usize, range in 0_usize..4096_usize) {
+            let remove_range = lower..lower + range;
+            let insert_range = (256_usize, 4095_usize);
+            let tree = TreeIndex::default();
+            for k in insert_range.0..=insert_range.1 {
+                prop_assert!(tree.insert(k, k).is_ok());
+            }
+            if usize::BITS == 32 {
+                prop_assert_eq!(tree.depth(), 4);
+            } else {
+                prop_assert_eq!(tree.depth(), 3);
+            }
+            tree.remove_range(remove_range.clone());
+            if remove_range.contains(&insert_range.0) && remove_range.contains(&insert_range.1) {
+                prop_assert!(tree.is_empty());
+            }
+            for (k, v) in tree.iter(&Guard::new()) {
+                prop_assert_eq!(k, v);
+                prop_assert!(!remove_range.contains(k), "{k}");
+            }
+            for k in 0_usize..4096_usize {
+                if tree.peek_with(&k, |_, _|()).is_some() {
+                    prop_assert!(!remove_range.contains(&k), "{k}");
+                }
+            }
+            for k in remove_range.clone() {
+                prop_assert!(tree.insert(k, k).is_ok());
+            }
+            let mut cnt = 0;
+            for (k, v) in tree.iter(&Guard::new()) {
+                prop_assert_eq!(k, v);
+                if remove_range.contains(k) {
+                    cnt += 1;
+                }
+            }
+            assert_eq!(cnt, range);
+        }
+
+        #[cfg_attr(miri, ignore)]
+        #[test]
+        fn prop_op_log(ops in prop::collection::vec((0_usize..4, any::<bool>(), 0_usize..256), 0..1024)) {
+            let num_threads = 4;
+            let tree = Arc::new(TreeIndex::default());
+            let op_log = Arc::new(OpLog::default());
+            let barrier = Arc::new(Barrier::new(num_threads));
+            let mut thread_handles = Vec::with_capacity(num_threads);
+            for thread_id in 0..num_threads {
+                // Each thread owns a disjoint set of keys.
+                let thread_ops = ops
+                    .iter()
+                    .filter(|(t, _, _)| *t == thread_id)
+                    .map(|(_, insert, k)| {
+                        let key = k * num_threads + thread_id;
+                        if *insert {
+                            Op::Insert(key, *k)
+                        } else {
+                            Op::Remove(key)
+                        }
+                    })
+                    .collect::<Vec<_>>();
+                let tree = tree.clone();
+                let op_log = op_log.clone();
+                let barrier = barrier.clone();
+                thread_handles.push(thread::spawn(move || {
+                    barrier.wait();
+                    for op in thread_ops {
+                        op_log.apply(&tree, op);
+                    }
+                }));
+            }
+            for t in thread_handles {
+                t.join().unwrap();
+            }
+            prop_assert_eq!(op_log.reconcile(&tree), Ok(()));
         }
     }
 }
@@ -5,19 +5,27 @@ mod leaf;
 mod leaf_node;
 mod node;
 
-use crate::ebr::{AtomicShared, Guard, Ptr, Shared, Tag};
+use crate::ebr::{AtomicShared, Epoch, Guard, Ptr, Shared, Tag};
+use crate::exit_guard::ExitGuard;
+use crate::maybe_std::{yield_now, AtomicUsize};
 use crate::wait_queue::AsyncWait;
-use leaf::{InsertResult, Leaf, RemoveResult, Scanner};
-use node::Node;
+use crate::LinkedList;
+use leaf::{InsertResult, Leaf, RemoveResult, Scanner, UpdateResult};
+use node::{Locker, Node};
 use std::borrow::Borrow;
 use std::cmp::Ordering;
+use std::collections::{BTreeSet, VecDeque};
 use std::fmt::{self, Debug};
+use std::hash::{Hash, Hasher};
 use std::iter::FusedIterator;
 use std::ops::Bound::{Excluded, Included, Unbounded};
-use std::ops::RangeBounds;
+use std::ops::{Add, Bound, RangeBounds};
 use std::panic::UnwindSafe;
 use std::pin::Pin;
-use std::sync::atomic::Ordering::{AcqRel, Acquire};
+use std::ptr;
+use std::rc::Rc;
+use std::sync::atomic::Ordering::{AcqRel, Acquire, Release};
+use std::time::Instant;
 
 /// Scalable concurrent B-plus tree.
 ///
@@ -57,6 +65,9 @@ use std::sync::atomic::Ordering::{AcqRel, Acquire};
 /// condition; `K::drop` and `V::drop` must not panic.
 pub struct TreeIndex<K, V> {
     root: AtomicShared<Node<K, V>>,
+    num_replacements: AtomicUsize,
+    rotate_len: AtomicUsize,
+    rotate_len_replacements: AtomicUsize,
 }
 
 /// An iterator over the entries of a [`TreeIndex`].
@@ -65,6 +76,8 @@ pub struct TreeIndex<K, V> {
 /// order.
 pub struct Iter<'t, 'g, K, V> {
     root: &'t AtomicShared<Node<K, V>>,
+    num_replacements: &'t AtomicUsize,
+    num_replacements_at_start: usize,
     leaf_scanner: Option<Scanner<'g, K, V>>,
     guard: &'g Guard,
 }
@@ -79,6 +92,188 @@ pub struct Range<'t, 'g, K, V, R: RangeBounds<K>> {
     guard: &'g Guard,
 }
 
+/// An owned token that marks the position of a [`Range`] scan.
+///
+/// A [`ScanToken`] does not hold a [`Guard`], and a scan can be resumed from it later with a new
+/// [`Guard`] by [`TreeIndex::resume`].
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct ScanToken<K> {
+    start: Bound<K>,
+    end: Bound<K>,
+}
+
+/// The result of [`TreeIndex::for_each_until_deadline`].
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum ScanStatus<K> {
+    /// All the entries were visited.
+    Completed,
+
+    /// The deadline passed after the entry with the key was visited.
+    TimedOut(K),
+}
+
+/// A difference between two [`TreeIndex`] instances reported by [`TreeIndex::diff`].
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Diff<'g, K, V> {
+    /// The entry only exists in the other [`TreeIndex`].
+    Added(&'g K, &'g V),
+
+    /// The entry only exists in the [`TreeIndex`].
+    Removed(&'g K, &'g V),
+
+    /// The key exists in both, and is associated with the old value in the [`TreeIndex`] and the
+    /// new value in the other one.
+    Changed(&'g K, &'g V, &'g V),
+}
+
+/// An error returned by [`TreeIndex::insert_bounded`] along with the supplied key-value pair.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum InsertError<K, V> {
+    /// The key exists.
+    Duplicate(K, V),
+
+    /// The key-value pair cannot be inserted without making the [`TreeIndex`] deeper than the
+    /// maximum depth.
+    Overflow(K, V),
+}
+
+/// Occupancy statistics of a leaf in a [`TreeIndex`].
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct LeafInfo<'g, K> {
+    /// The minimum key in the leaf.
+    pub min_key: Option<&'g K>,
+
+    /// The maximum key in the leaf.
+    pub max_key: Option<&'g K>,
+
+    /// The number of reachable entries in the leaf.
+    pub len: usize,
+
+    /// The maximum number of entries that the leaf can contain.
+    pub capacity: usize,
+}
+
+/// A staging buffer that coalesces insertions into a [`TreeIndex`].
+///
+/// Entries are sorted and inserted into the [`TreeIndex`] in key order once the buffer fills up,
+/// when [`InsertBuffer::flush`] is called, or when the [`InsertBuffer`] is dropped. Entries
+/// become visible to readers as they land in leaves, and are not visible while they are staged.
+pub struct InsertBuffer<'t, K, V>
+where
+    K: 'static + Clone + Ord,
+    V: 'static + Clone,
+{
+    tree: &'t TreeIndex<K, V>,
+    staged: Vec<(K, V)>,
+    rejected: Vec<(K, V)>,
+    capacity: usize,
+}
+
+/// A handle to a [`TreeIndex`] that reuses a [`Guard`] across operations.
+///
+/// The [`Guard`] is replaced with a new one after a configurable number of operations, so that a
+/// long-lived [`Pinned`] does not indefinitely prevent retired memory from being reclaimed.
+pub struct Pinned<'t, K, V> {
+    tree: &'t TreeIndex<K, V>,
+    guard: Option<Guard>,
+    num_ops: usize,
+    repin_interval: usize,
+}
+
+/// A [`Stream`](futures_core::Stream) of clones of the entries in a [`TreeIndex`].
+///
+/// A [`Stream`] does not hold a [`Guard`] between polls; it clones the entries of one leaf at a
+/// time, and resumes the scan after the last key it has cloned when the entries run out.
+#[cfg(feature = "stream")]
+pub struct Stream<'t, K, V> {
+    tree: &'t TreeIndex<K, V>,
+    entries: VecDeque<(K, V)>,
+    last_key: Option<K>,
+}
+
+/// An [`Iterator`] over the entries taken out of a [`TreeIndex`] by [`TreeIndex::take_all`].
+///
+/// [`TakeAll`] owns the detached tree, and clones the entries of one leaf at a time when it moves
+/// on to the leaf; a [`Guard`] is only held while they are being cloned. The detached tree is
+/// cleared when the [`TakeAll`] is exhausted or dropped.
+pub struct TakeAll<K, V>
+where
+    K: 'static + Clone + Ord,
+    V: 'static + Clone,
+{
+    root: AtomicShared<Node<K, V>>,
+    entries: VecDeque<(K, V)>,
+    last_key: Option<K>,
+}
+
+/// The cost of a lookup reported by [`TreeIndex::get_profiled`].
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub struct DescentProfile {
+    /// The number of nodes descended through, which is equal to the depth of the [`TreeIndex`].
+    pub levels: usize,
+
+    /// The number of key comparisons in the nodes and the leaf.
+    pub comparisons: usize,
+}
+
+/// The number of entries in a [`TreeIndex`] at a point of time, taken by
+/// [`TreeIndex::count_snapshot`].
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct CountSnapshot {
+    /// The number of entries.
+    pub len: usize,
+
+    /// The [`Epoch`] the entries were counted in.
+    ///
+    /// Two snapshots taken in the same [`Epoch`] may have observed partially the same
+    /// modifications, and a difference of two epochs is only meaningful modulo four.
+    pub epoch: Epoch,
+
+    /// The time the entries were counted at.
+    pub taken_at: Instant,
+}
+
+impl CountSnapshot {
+    /// Returns the change in the number of entries since `earlier`.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use scc::TreeIndex;
+    /// use scc::ebr::Guard;
+    ///
+    /// let treeindex: TreeIndex<u64, u32> = TreeIndex::new();
+    ///
+    /// let earlier = treeindex.count_snapshot(&Guard::new());
+    /// assert!(treeindex.insert(1, 10).is_ok());
+    /// let later = treeindex.count_snapshot(&Guard::new());
+    /// assert_eq!(later.delta(&earlier), 1);
+    /// assert_eq!(earlier.delta(&later), -1);
+    /// ```
+    #[inline]
+    #[must_use]
+    #[allow(clippy::cast_possible_wrap)]
+    pub fn delta(&self, earlier: &CountSnapshot) -> isize {
+        self.len.wrapping_sub(earlier.len) as isize
+    }
+}
+
+/// Structural information of a node in a [`TreeIndex`].
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct NodeInfo<'g, K> {
+    /// The distance from the root; the root is at level `0`.
+    pub level: usize,
+
+    /// `true` if the children of the node are leaves containing entries.
+    pub is_leaf_node: bool,
+
+    /// The number of children including the unbounded child.
+    pub num_children: usize,
+
+    /// The separator keys of the bounded children in ascending order.
+    pub separators: Vec<&'g K>,
+}
+
 impl<K, V> TreeIndex<K, V> {
     /// Creates an empty [`TreeIndex`].
     ///
@@ -95,6 +290,9 @@ impl<K, V> TreeIndex<K, V> {
     pub const fn new() -> Self {
         Self {
             root: AtomicShared::null(),
+            num_replacements: AtomicUsize::new(0),
+            rotate_len: AtomicUsize::new(0),
+            rotate_len_replacements: AtomicUsize::new(usize::MAX),
         }
     }
 
@@ -105,6 +303,9 @@ impl<K, V> TreeIndex<K, V> {
     pub fn new() -> Self {
         Self {
             root: AtomicShared::null(),
+            num_replacements: AtomicUsize::new(0),
+            rotate_len: AtomicUsize::new(0),
+            rotate_len_replacements: AtomicUsize::new(usize::MAX),
         }
     }
 
@@ -123,6 +324,7 @@ impl<K, V> TreeIndex<K, V> {
     #[inline]
     pub fn clear(&self) {
         if let (Some(root), _) = self.root.swap((None, Tag::None), Acquire) {
+            self.num_replacements.fetch_add(1, Release);
             root.clear(&Guard::new());
         }
     }
@@ -170,55 +372,318 @@ where
     /// assert_eq!(treeindex.peek_with(&1, |k, v| *v).unwrap(), 10);
     /// ```
     #[inline]
-    pub fn insert(&self, mut key: K, mut val: V) -> Result<(), (K, V)> {
-        let mut new_root = None;
+    pub fn insert(&self, key: K, val: V) -> Result<(), (K, V)> {
+        self.insert_with_guard(key, val, usize::MAX, &Guard::new())
+            .map_err(InsertError::into_entry)
+    }
+
+    /// Inserts a key-value pair if the depth of the [`TreeIndex`] can be kept equal to or less
+    /// than `max_depth`.
+    ///
+    /// The [`TreeIndex`] grows deeper only when the root node is created or split, therefore the
+    /// key-value pair is rejected if the root node is full and the [`TreeIndex`] is already
+    /// `max_depth` levels deep; the worst-case lookup latency is thus bounded by `max_depth`.
+    /// Key-value pairs may still be inserted into nodes that are not full.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`InsertError::Duplicate`] if the key exists, and [`InsertError::Overflow`] if the
+    /// root node has to be split, along with the supplied key-value pair.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use scc::TreeIndex;
+    /// use scc::tree_index::InsertError;
+    ///
+    /// let treeindex: TreeIndex<u64, u32> = TreeIndex::new();
+    ///
+    /// assert!(treeindex.insert_bounded(1, 10, 1).is_ok());
+    /// assert_eq!(treeindex.insert_bounded(1, 11, 1), Err(InsertError::Duplicate(1, 11)));
+    ///
+    /// let mut k = 2;
+    /// while treeindex.insert_bounded(k, 0, 1).is_ok() {
+    ///     k += 1;
+    /// }
+    /// assert_eq!(treeindex.insert_bounded(k, 0, 1), Err(InsertError::Overflow(k, 0)));
+    /// assert_eq!(treeindex.depth(), 1);
+    /// ```
+    #[inline]
+    pub fn insert_bounded(
+        &self,
+        key: K,
+        val: V,
+        max_depth: usize,
+    ) -> Result<(), InsertError<K, V>> {
+        self.insert_with_guard(key, val, max_depth, &Guard::new())
+    }
+
+    /// Inserts a key-value pair if the key does not exist.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error along with a clone of the value associated with the key if the key
+    /// exists.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use scc::TreeIndex;
+    ///
+    /// let treeindex: TreeIndex<u64, u32> = TreeIndex::new();
+    ///
+    /// assert!(treeindex.insert_unique(1, 10).is_ok());
+    /// assert_eq!(treeindex.insert_unique(1, 11), Err(10));
+    /// ```
+    #[inline]
+    pub fn insert_unique(&self, key: K, val: V) -> Result<(), V> {
+        let (mut key, mut val) = (key, val);
         loop {
-            let guard = Guard::new();
-            let root_ptr = self.root.load(Acquire, &guard);
-            if let Some(root_ref) = root_ptr.as_ref() {
-                match root_ref.insert(key, val, &mut (), &guard) {
-                    Ok(r) => match r {
-                        InsertResult::Success => return Ok(()),
-                        InsertResult::Frozen(k, v) | InsertResult::Retry(k, v) => {
-                            key = k;
-                            val = v;
-                            root_ref.cleanup_link(&key, false, &guard);
-                        }
-                        InsertResult::Duplicate(k, v) => return Err((k, v)),
-                        InsertResult::Full(k, v) => {
-                            let (k, v) = Node::split_root(root_ptr, &self.root, k, v, &guard);
-                            key = k;
-                            val = v;
-                            continue;
-                        }
-                        InsertResult::Retired(k, v) => {
-                            key = k;
-                            val = v;
-                            let _result = Node::cleanup_root(&self.root, &mut (), &guard);
-                        }
-                    },
-                    Err((k, v)) => {
-                        key = k;
-                        val = v;
+            match self.insert(key, val) {
+                Ok(()) => return Ok(()),
+                Err((k, v)) => {
+                    if let Some(existing) = self.peek_with(&k, |_, existing| existing.clone()) {
+                        return Err(existing);
                     }
+                    // The entry was removed in the meantime.
+                    key = k;
+                    val = v;
                 }
             }
+        }
+    }
 
-            let node = if let Some(new_root) = new_root.take() {
-                new_root
-            } else {
-                Shared::new(Node::new_leaf_node())
-            };
-            if let Err((node, _)) = self.root.compare_exchange(
-                Ptr::null(),
-                (Some(node), Tag::None),
-                AcqRel,
-                Acquire,
-                &guard,
-            ) {
-                new_root = node;
+    /// Adds `delta` to the value associated with the key, or inserts `delta` if the key does not
+    /// exist.
+    ///
+    /// Returns the resulting value. Concurrent increments of the same key are never lost.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use scc::TreeIndex;
+    ///
+    /// let treeindex: TreeIndex<u64, u32> = TreeIndex::new();
+    ///
+    /// assert_eq!(treeindex.increment(1, 10), 10);
+    /// assert_eq!(treeindex.increment(1, 5), 15);
+    /// assert_eq!(treeindex.peek_with(&1, |_, v| *v), Some(15));
+    /// ```
+    #[inline]
+    pub fn increment(&self, key: K, delta: V) -> V
+    where
+        V: Add<Output = V>,
+    {
+        let mut key = key;
+        loop {
+            let mut result = None;
+            if self.update(&key, |v| {
+                let new = v.clone() + delta.clone();
+                result.replace(new.clone());
+                Some(new)
+            }) {
+                if let Some(result) = result {
+                    return result;
+                }
+            }
+            match self.insert(key, delta.clone()) {
+                Ok(()) => return delta,
+                // The key was inserted in the meantime.
+                Err((k, _)) => key = k,
+            }
+        }
+    }
+
+    /// Combines `val` with the value associated with the key, or inserts `val` if the key does not
+    /// exist.
+    ///
+    /// The existing value is replaced with `combine(existing, val)`, and the resulting value is
+    /// returned. `combine` may be invoked more than once under contention, and it has to be
+    /// associative for concurrent merges of the same key to converge regardless of their order.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use scc::TreeIndex;
+    ///
+    /// let treeindex: TreeIndex<u64, u32> = TreeIndex::new();
+    ///
+    /// assert_eq!(treeindex.merge(1, 10, |a, b| *a.max(b)), 10);
+    /// assert_eq!(treeindex.merge(1, 5, |a, b| *a.max(b)), 10);
+    /// assert_eq!(treeindex.merge(1, 15, |a, b| *a.max(b)), 15);
+    /// assert_eq!(treeindex.peek_with(&1, |_, v| *v), Some(15));
+    /// ```
+    #[inline]
+    pub fn merge<F: Fn(&V, &V) -> V>(&self, key: K, val: V, combine: F) -> V {
+        let mut key = key;
+        loop {
+            let mut result = None;
+            if self.update(&key, |v| {
+                let new = combine(v, &val);
+                result.replace(new.clone());
+                Some(new)
+            }) {
+                if let Some(result) = result {
+                    return result;
+                }
+            }
+            match self.insert(key, val.clone()) {
+                Ok(()) => return val,
+                // The key was inserted in the meantime.
+                Err((k, _)) => key = k,
+            }
+        }
+    }
+
+    /// Inserts a key-value pair, or replaces the value if the key exists.
+    ///
+    /// Returns `true` if the key-value pair was newly inserted. If the key exists, the value is
+    /// replaced with the supplied one, a clone of the previous value is written into `displaced`,
+    /// and `false` is returned. `displaced` is not modified if the key-value pair was newly
+    /// inserted.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use scc::TreeIndex;
+    ///
+    /// let treeindex: TreeIndex<u64, u32> = TreeIndex::new();
+    /// let mut displaced = None;
+    ///
+    /// assert!(treeindex.insert_into(1, 10, &mut displaced));
+    /// assert!(displaced.is_none());
+    /// assert!(!treeindex.insert_into(1, 11, &mut displaced));
+    /// assert_eq!(displaced, Some(10));
+    /// assert_eq!(treeindex.peek_with(&1, |_, v| *v), Some(11));
+    /// ```
+    #[inline]
+    pub fn insert_into(&self, key: K, val: V, displaced: &mut Option<V>) -> bool
+    where
+        V: Clone,
+    {
+        let (mut key, mut val) = (key, val);
+        loop {
+            match self.insert(key, val) {
+                Ok(()) => return true,
+                Err((k, v)) => {
+                    let mut prev_val = None;
+                    if self.update(&k, |prev| {
+                        prev_val.replace(prev.clone());
+                        Some(v.clone())
+                    }) {
+                        *displaced = prev_val;
+                        return false;
+                    }
+
+                    // The entry was removed in the meantime.
+                    (key, val) = (k, v);
+                }
+            }
+        }
+    }
+
+    /// Inserts a key-value pair that is expected to be greater than any other key.
+    ///
+    /// The entry is directly inserted into the rightmost leaf without traversing the tree from
+    /// the root if the key is greater than the maximum key in the leaf; otherwise, it falls back
+    /// to [`TreeIndex::insert`]. This is beneficial for workloads inserting monotonically
+    /// increasing keys.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error along with the supplied key-value pair if the key exists.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use scc::TreeIndex;
+    ///
+    /// let treeindex: TreeIndex<u64, u32> = TreeIndex::new();
+    ///
+    /// assert!(treeindex.insert_at_tail(1, 10).is_ok());
+    /// assert!(treeindex.insert_at_tail(2, 20).is_ok());
+    /// assert!(treeindex.insert_at_tail(0, 0).is_ok());
+    /// assert_eq!(treeindex.insert_at_tail(2, 21).err().unwrap(), (2, 21));
+    /// assert_eq!(treeindex.len(), 3);
+    /// ```
+    #[inline]
+    pub fn insert_at_tail(&self, key: K, val: V) -> Result<(), (K, V)> {
+        let guard = Guard::new();
+        if let Some(leaf) = self
+            .root
+            .load(Acquire, &guard)
+            .as_ref()
+            .and_then(|root_ref| root_ref.unbounded_leaf(&guard))
+        {
+            // Keys greater than the maximum key in the rightmost leaf always belong to the leaf.
+            if matches!(leaf.max_key(), Some(max_key) if *max_key < key) {
+                match leaf.insert(key, val) {
+                    InsertResult::Success => return Ok(()),
+                    InsertResult::Duplicate(k, v)
+                    | InsertResult::Full(k, v)
+                    | InsertResult::Frozen(k, v)
+                    | InsertResult::Retired(k, v)
+                    | InsertResult::Retry(k, v) => return self.insert(k, v),
+                }
             }
         }
+        self.insert(key, val)
+    }
+
+    /// Returns a [`Pinned`] handle that reuses a [`Guard`] for up to `repin_interval` operations.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use scc::TreeIndex;
+    ///
+    /// let treeindex: TreeIndex<u64, u32> = TreeIndex::new();
+    ///
+    /// let mut pinned = treeindex.pinned(64);
+    /// assert!(pinned.insert(1, 10).is_ok());
+    /// assert_eq!(pinned.peek(&1), Some(&10));
+    /// assert!(pinned.remove(&1));
+    /// ```
+    #[inline]
+    pub fn pinned(&self, repin_interval: usize) -> Pinned<'_, K, V> {
+        Pinned {
+            tree: self,
+            guard: None,
+            num_ops: 0,
+            repin_interval: repin_interval.max(1),
+        }
+    }
+
+    /// Returns an [`InsertBuffer`] that stages up to `capacity` entries before inserting them into
+    /// the [`TreeIndex`] in key order.
+    ///
+    /// Consecutive staged keys that fall into the same leaf are inserted without traversing the
+    /// tree from the root, which is beneficial for bursts of insertions of adjacent keys.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use scc::TreeIndex;
+    ///
+    /// let treeindex: TreeIndex<u64, u32> = TreeIndex::new();
+    ///
+    /// let mut buffer = treeindex.insert_buffered(4);
+    /// buffer.insert(2, 20);
+    /// buffer.insert(1, 10);
+    /// assert!(!treeindex.contains(&1));
+    ///
+    /// assert!(buffer.flush().is_empty());
+    /// assert!(treeindex.contains(&1));
+    /// assert!(treeindex.contains(&2));
+    /// ```
+    #[inline]
+    pub fn insert_buffered(&self, capacity: usize) -> InsertBuffer<'_, K, V> {
+        InsertBuffer {
+            tree: self,
+            staged: Vec::with_capacity(capacity),
+            rejected: Vec::new(),
+            capacity: capacity.max(1),
+        }
     }
 
     /// Inserts a key-value pair.
@@ -378,40 +843,7 @@ where
         K: Borrow<Q>,
         Q: Ord + ?Sized,
     {
-        let mut removed = false;
-        loop {
-            let guard = Guard::new();
-            if let Some(root_ref) = self.root.load(Acquire, &guard).as_ref() {
-                if let Ok(result) =
-                    root_ref.remove_if::<_, _, _>(key, &mut condition, &mut (), &guard)
-                {
-                    if matches!(result, RemoveResult::Cleanup) {
-                        root_ref.cleanup_link(key, false, &guard);
-                    }
-                    match result {
-                        RemoveResult::Success => return true,
-                        RemoveResult::Cleanup | RemoveResult::Retired => {
-                            if Node::cleanup_root(&self.root, &mut (), &guard) {
-                                return true;
-                            }
-                            removed = true;
-                        }
-                        RemoveResult::Fail => {
-                            if removed {
-                                if Node::cleanup_root(&self.root, &mut (), &guard) {
-                                    return true;
-                                }
-                            } else {
-                                return false;
-                            }
-                        }
-                        RemoveResult::Frozen => (),
-                    }
-                }
-            } else {
-                return removed;
-            }
-        }
+        self.remove_if_with_guard(key, &mut condition, &Guard::new())
     }
 
     /// Removes a key-value pair if the given condition is met.
@@ -593,37 +1025,3543 @@ where
         }
     }
 
-    /// Returns a guarded reference to the value for the specified key without acquiring locks.
+    /// Replaces the values of the given keys if the current values are equal to the expected
+    /// ones.
+    ///
+    /// Each `(key, expected, new)` update is applied independently, and the returned [`Vec`]
+    /// tells whether the corresponding update succeeded. Readers observe either the old or the new
+    /// value of an entry. Updates are applied in ascending key order, and in the given order for
+    /// the same key; the leaf found for a key is reused for the following keys as long as they
+    /// belong to it.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use scc::TreeIndex;
+    ///
+    /// let treeindex: TreeIndex<u64, u32> = TreeIndex::new();
+    ///
+    /// assert!(treeindex.insert(1, 10).is_ok());
+    /// assert!(treeindex.insert(2, 20).is_ok());
+    /// assert_eq!(
+    ///     treeindex.compare_and_swap_many(&[(1, 10, 11), (2, 0, 21), (3, 30, 31)]),
+    ///     vec![true, false, false]
+    /// );
+    /// assert_eq!(treeindex.peek_with(&1, |_, v| *v), Some(11));
+    /// assert_eq!(treeindex.peek_with(&2, |_, v| *v), Some(20));
+    /// ```
+    #[inline]
+    pub fn compare_and_swap_many(&self, updates: &[(K, V, V)]) -> Vec<bool>
+    where
+        V: PartialEq,
+    {
+        let mut order = (0..updates.len()).collect::<Vec<_>>();
+        order.sort_by(|a, b| updates[*a].0.cmp(&updates[*b].0));
+
+        let mut results = vec![false; updates.len()];
+        let guard = Guard::new();
+        let mut cached_leaf: Option<(&Leaf<K, V>, Option<&K>)> = None;
+        for i in order {
+            let (key, expected, new) = &updates[i];
+            if !matches!(cached_leaf, Some((_, bound)) if bound.map_or(true, |b| key <= b)) {
+                cached_leaf = self
+                    .root
+                    .load(Acquire, &guard)
+                    .as_ref()
+                    .and_then(|root_ref| root_ref.search_leaf(key, None, &guard));
+            }
+            if let Some((leaf, _)) = cached_leaf {
+                // The comparison is conclusive once the value was read from a leaf that accepts
+                // updates, whereas the key may have been moved to another leaf otherwise.
+                let mut compared = false;
+                match leaf.update(key, &mut |v: &V| {
+                    compared = true;
+                    (v == expected).then(|| new.clone())
+                }) {
+                    UpdateResult::Success => {
+                        results[i] = true;
+                        continue;
+                    }
+                    UpdateResult::Fail if compared => continue,
+                    _ => (),
+                }
+            }
+            cached_leaf = None;
+            results[i] = self.update(key, |v| (v == expected).then(|| new.clone()));
+        }
+        results
+    }
+
+    /// Modifies every value in ascending key order.
+    ///
+    /// Leaves are visited one after another, and `f` is invoked on a copy of each value that then
+    /// atomically replaces the value in the leaf; readers observe either the old or the new value.
+    /// `f` may be invoked more than once for an entry if the entry is concurrently modified or the
+    /// leaf has no vacant slot for the new value, and entries inserted by other threads during
+    /// the sweep may not be visited.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use scc::TreeIndex;
+    ///
+    /// let treeindex: TreeIndex<u64, u32> = TreeIndex::new();
+    ///
+    /// for k in 0..64 {
+    ///     assert!(treeindex.insert(k, k as u32).is_ok());
+    /// }
+    ///
+    /// treeindex.for_each_mut(|_, v| *v *= 2);
+    /// assert_eq!(treeindex.peek_with(&7, |_, v| *v), Some(14));
+    /// ```
+    #[inline]
+    pub fn for_each_mut<F: FnMut(&K, &mut V)>(&self, mut f: F) {
+        let guard = Guard::new();
+        let Some(mut scanner) = self
+            .root
+            .load(Acquire, &guard)
+            .as_ref()
+            .and_then(|root_ref| root_ref.min(&guard))
+        else {
+            return;
+        };
+        let mut entry = scanner.next();
+        let mut last_key: Option<&K> = None;
+        while let Some((key, _)) = entry {
+            if last_key.map_or(true, |last_key| last_key < key) {
+                let mut updater = |v: &V| {
+                    let mut new_val = v.clone();
+                    f(key, &mut new_val);
+                    Some(new_val)
+                };
+                match scanner.leaf().update(key, &mut updater) {
+                    UpdateResult::Success | UpdateResult::Fail => (),
+                    UpdateResult::Full(..) | UpdateResult::Frozen | UpdateResult::Retired => {
+                        // The leaf needs to be split, or is being split.
+                        self.update(key, &mut updater);
+                    }
+                }
+                last_key.replace(key);
+            }
+            entry = scanner.next();
+            if entry.is_none() {
+                if let Some(next_scanner) = scanner.jump(last_key, &guard) {
+                    entry = next_scanner.get();
+                    scanner = next_scanner;
+                }
+            }
+        }
+    }
+
+    /// Computes a new value for each of the keys in ascending key order.
+    ///
+    /// `f` receives the key and its current value, and returns the value to store; returning
+    /// `None` removes the entry, or leaves the key absent. The leaf found for a key is reused for
+    /// the following keys as long as they belong to it, so that sorted or clustered keys do not
+    /// require a descent from the root each. Each key is computed atomically on its own, and `f`
+    /// may be invoked more than once for a key if the entry is concurrently modified.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use scc::TreeIndex;
+    ///
+    /// let treeindex: TreeIndex<u64, u32> = TreeIndex::new();
+    ///
+    /// assert!(treeindex.insert(1, 10).is_ok());
+    /// assert!(treeindex.insert(2, 20).is_ok());
+    ///
+    /// treeindex.compute_many(&[3, 2, 1], |k, v| match (k, v) {
+    ///     (2, _) => None,
+    ///     (_, v) => Some(v.map_or(0, |v| v + 1)),
+    /// });
+    /// assert_eq!(treeindex.peek_with(&1, |_, v| *v), Some(11));
+    /// assert!(!treeindex.contains(&2));
+    /// assert_eq!(treeindex.peek_with(&3, |_, v| *v), Some(0));
+    /// ```
+    #[inline]
+    pub fn compute_many<F: FnMut(&K, Option<&V>) -> Option<V>>(&self, keys: &[K], mut f: F) {
+        let mut sorted = keys.iter().collect::<Vec<_>>();
+        sorted.sort_unstable();
+
+        let guard = Guard::new();
+        let mut cached_leaf: Option<(&Leaf<K, V>, Option<&K>)> = None;
+        for key in sorted {
+            if !matches!(cached_leaf, Some((_, bound)) if bound.map_or(true, |b| key <= b)) {
+                cached_leaf = self
+                    .root
+                    .load(Acquire, &guard)
+                    .as_ref()
+                    .and_then(|root_ref| root_ref.search_leaf(key, None, &guard));
+            }
+            if let Some((leaf, _)) = cached_leaf {
+                // Only a successful update or removal is conclusive, since the key may have been
+                // moved to another leaf if the leaf is not accepting updates.
+                let mut removal = None;
+                match leaf.update(key, &mut |v: &V| {
+                    let new_val = f(key, Some(v));
+                    if new_val.is_none() {
+                        removal.replace(v as *const V);
+                    }
+                    new_val
+                }) {
+                    UpdateResult::Success => continue,
+                    UpdateResult::Fail => {
+                        if let Some(removal) = removal {
+                            if self.remove_computed(key, removal, &guard) {
+                                continue;
+                            }
+                        }
+                    }
+                    _ => (),
+                }
+            }
+            cached_leaf = None;
+            self.compute(key, &mut f, &guard);
+        }
+    }
+
+    /// Rebuilds the internal structure of the [`TreeIndex`] from the entries stored in leaves.
+    ///
+    /// It collects all the entries by traversing the linked list of leaves, bulk-loads them into a
+    /// new tree, and then atomically replaces the root with the new one. This is a recovery tool
+    /// that also makes the tree compact.
+    ///
+    /// # Notes
+    ///
+    /// The [`TreeIndex`] is frozen while it is being rebuilt: threads trying to insert, remove, or
+    /// update entries wait until the new root is installed, and then retry on the new tree,
+    /// therefore no updates are lost. Readers are not blocked.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use scc::TreeIndex;
+    ///
+    /// let treeindex: TreeIndex<u64, u32> = TreeIndex::new();
+    ///
+    /// for k in 0..64 {
+    ///     assert!(treeindex.insert(k, 0).is_ok());
+    /// }
+    /// treeindex.remove_range(8..56);
+    ///
+    /// treeindex.rebuild_from_leaves();
+    /// assert_eq!(treeindex.len(), 16);
+    /// assert_eq!(treeindex.depth(), 1);
+    /// ```
+    #[inline]
+    pub fn rebuild_from_leaves(&self) {
+        let guard = Guard::new();
+        loop {
+            let mut lockers = Vec::new();
+            let root_ptr = self.freeze(&mut lockers, &guard);
+            let rebuilt = TreeIndex::new();
+            for (k, v) in self.iter(&guard) {
+                let _result = rebuilt.insert_at_tail(k.clone(), v.clone());
+            }
+            if self.install_root(&rebuilt, Some(root_ptr), &guard) {
+                // Waiting threads retry on the new tree once the old one is unlocked.
+                drop(lockers);
+                return;
+            }
+            Self::thaw(lockers, &guard);
+        }
+    }
+
+    /// Checks if all the leaves are at the same depth, and rebuilds the [`TreeIndex`] if not.
+    ///
+    /// Returns `true` if the [`TreeIndex`] was unbalanced and has been rebuilt from the entries
+    /// found by traversing the nodes, in the same way as [`TreeIndex::rebuild_from_leaves`]. Nodes
+    /// that are concurrently split or merged are checked as they are seen during the traversal.
+    ///
+    /// # Notes
+    ///
+    /// The [`TreeIndex`] is frozen only if it is found unbalanced, and then threads trying to
+    /// modify it wait until the rebuilt tree is installed, as is the case with
+    /// [`TreeIndex::rebuild_from_leaves`].
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use scc::TreeIndex;
+    ///
+    /// let treeindex: TreeIndex<u64, u32> = TreeIndex::new();
+    ///
+    /// for k in 0..256 {
+    ///     assert!(treeindex.insert(k, 0).is_ok());
+    /// }
+    /// assert!(!treeindex.ensure_balanced());
+    /// ```
+    #[inline]
+    pub fn ensure_balanced(&self) -> bool {
+        let guard = Guard::new();
+        let mut frozen = None;
+        loop {
+            let root_ptr = self.root.load(Acquire, &guard);
+            let Some(root_ref) = root_ptr.as_ref() else {
+                if let Some(lockers) = frozen {
+                    Self::thaw(lockers, &guard);
+                }
+                return false;
+            };
+            let mut leaf_depth = None;
+            let mut balanced = true;
+            let mut entries = Vec::new();
+            root_ref.for_each_leaf(
+                &mut Vec::new(),
+                &mut |path, leaf| {
+                    if *leaf_depth.get_or_insert(path.len()) != path.len() {
+                        balanced = false;
+                    }
+                    entries.extend(Scanner::new(leaf));
+                },
+                &guard,
+            );
+            if balanced {
+                if let Some(lockers) = frozen {
+                    Self::thaw(lockers, &guard);
+                }
+                return false;
+            }
+            let Some(lockers) = frozen.take() else {
+                // The entries are collected again once concurrent updates are blocked.
+                let mut lockers = Vec::new();
+                self.freeze(&mut lockers, &guard);
+                frozen.replace(lockers);
+                continue;
+            };
+
+            // Entries are collected from the nodes instead of the linked list of leaves, since the
+            // linked list may be as broken as the nodes are.
+            let rebuilt = TreeIndex::new();
+            for (k, v) in entries {
+                let _result = rebuilt.insert_at_tail(k.clone(), v.clone());
+            }
+            if self.install_root(&rebuilt, Some(root_ptr), &guard) {
+                drop(lockers);
+                return true;
+            }
+            Self::thaw(lockers, &guard);
+        }
+    }
+
+    /// Replaces all the entries with the supplied ones.
+    ///
+    /// A new tree is bulk-loaded from `entries`, which are expected to be sorted in ascending key
+    /// order, and then the root is atomically replaced with that of the new tree; readers observe
+    /// either all the old entries or all the new ones. References obtained and iterators
+    /// advanced before the replacement keep referring to the old entries until they are dropped.
+    /// Later entries with the same key as an earlier one are ignored.
+    ///
+    /// # Notes
+    ///
+    /// Entries inserted or removed by other threads while the new tree is being built may not be
+    /// reflected in either tree.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use scc::TreeIndex;
+    /// use scc::ebr::Guard;
+    ///
+    /// let treeindex: TreeIndex<u64, u32> = TreeIndex::new();
+    ///
+    /// assert!(treeindex.insert(1, 10).is_ok());
+    /// assert!(treeindex.insert(2, 20).is_ok());
+    ///
+    /// let guard = Guard::new();
+    /// let mut iter = treeindex.iter(&guard);
+    /// assert_eq!(iter.next(), Some((&1, &10)));
+    ///
+    /// treeindex.replace_all((2..5).map(|k| (k, 0)));
+    /// assert_eq!(iter.next(), Some((&2, &20)));
+    /// assert!(!treeindex.contains(&1));
+    /// assert_eq!(treeindex.len(), 3);
+    /// ```
+    #[inline]
+    pub fn replace_all<I: IntoIterator<Item = (K, V)>>(&self, entries: I) {
+        let replacement = TreeIndex::new();
+        for (k, v) in entries {
+            let _result = replacement.insert_at_tail(k, v);
+        }
+        self.install_root(&replacement, None, &Guard::new());
+    }
+
+    /// Builds a new tree with the supplied closure, and replaces the root with that of the new
+    /// tree only if the closure succeeds.
+    ///
+    /// The closure fills the empty [`TreeIndex`] it receives, e.g., with entries read from `self`.
+    /// If the closure returns an error or panics, the new tree is dropped and `self` is left
+    /// untouched; otherwise, the root is atomically replaced, and readers observe either all the
+    /// old entries or all the new ones, as is the case with [`TreeIndex::replace_all`].
+    ///
+    /// # Errors
+    ///
+    /// Returns the error returned by the closure.
+    ///
+    /// # Notes
+    ///
+    /// `self` is frozen while the closure runs: threads trying to insert, remove, or update
+    /// entries wait until the closure returns, and then retry on whichever tree is installed,
+    /// therefore no updates are lost. The closure must not modify `self`, since it would wait for
+    /// itself forever.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use scc::TreeIndex;
+    /// use scc::ebr::Guard;
+    ///
+    /// let treeindex: TreeIndex<u64, u32> = TreeIndex::new();
+    ///
+    /// for k in 0..8 {
+    ///     assert!(treeindex.insert(k, 0).is_ok());
+    /// }
+    ///
+    /// let result = treeindex.atomic_rebuild(|rebuilt| {
+    ///     for (k, v) in treeindex.iter(&Guard::new()) {
+    ///         if *k == 4 {
+    ///             return Err(*k);
+    ///         }
+    ///         let _result = rebuilt.insert_at_tail(*k, *v + 1);
+    ///     }
+    ///     Ok(())
+    /// });
+    /// assert_eq!(result, Err(4));
+    /// assert_eq!(treeindex.peek_with(&0, |_, v| *v), Some(0));
+    ///
+    /// let result = treeindex.atomic_rebuild(|rebuilt| {
+    ///     for (k, v) in treeindex.iter(&Guard::new()) {
+    ///         let _result = rebuilt.insert_at_tail(*k, *v + 1);
+    ///     }
+    ///     Ok::<(), ()>(())
+    /// });
+    /// assert!(result.is_ok());
+    /// assert_eq!(treeindex.peek_with(&0, |_, v| *v), Some(1));
+    /// assert_eq!(treeindex.len(), 8);
+    /// ```
+    #[inline]
+    pub fn atomic_rebuild<E, F: FnOnce(&TreeIndex<K, V>) -> Result<(), E>>(
+        &self,
+        build: F,
+    ) -> Result<(), E> {
+        let guard = Guard::new();
+        let mut lockers = Vec::new();
+        self.freeze(&mut lockers, &guard);
+
+        // The tree is thawed if the closure fails or panics.
+        let mut lockers = ExitGuard::new(Some(lockers), |lockers| {
+            if let Some(lockers) = lockers {
+                Self::thaw(lockers, &guard);
+            }
+        });
+        let rebuilt = TreeIndex::new();
+        build(&rebuilt)?;
+        self.install_root(&rebuilt, None, &guard);
+        drop(lockers.take());
+        Ok(())
+    }
+
+    /// Clears the [`TreeIndex`], and returns an [`Iterator`] over the entries it held in ascending
+    /// key order.
+    ///
+    /// The root is atomically detached, therefore the returned entries are exactly those
+    /// reachable from the root at the moment, and the [`TreeIndex`] is empty afterwards. Readers
+    /// of the old tree keep observing the old entries until they are dropped.
+    ///
+    /// # Notes
+    ///
+    /// Entries inserted into the old tree by other threads that loaded the root before it was
+    /// detached may not be returned, as is the case with [`TreeIndex::clear`].
+    ///
+    /// The returned [`TakeAll`] clones the entries of one leaf at a time, since references to the
+    /// entries held by readers of the old tree may still be alive.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use scc::TreeIndex;
+    ///
+    /// let treeindex: TreeIndex<u64, u32> = TreeIndex::new();
+    ///
+    /// assert!(treeindex.insert(2, 20).is_ok());
+    /// assert!(treeindex.insert(1, 10).is_ok());
+    ///
+    /// assert_eq!(treeindex.take_all().collect::<Vec<_>>(), vec![(1, 10), (2, 20)]);
+    /// assert!(treeindex.is_empty());
+    /// assert!(treeindex.take_all().next().is_none());
+    /// ```
+    #[inline]
+    pub fn take_all(&self) -> TakeAll<K, V> {
+        let root = self.root.swap((None, Tag::None), AcqRel).0.map_or_else(
+            AtomicShared::null,
+            |old_root| {
+                self.num_replacements.fetch_add(1, Release);
+                AtomicShared::from(old_root)
+            },
+        );
+        TakeAll {
+            root,
+            entries: VecDeque::new(),
+            last_key: None,
+        }
+    }
+
+    /// Returns a new [`TreeIndex`] with the same keys and the values transformed by `f`.
+    ///
+    /// Entries are scanned in ascending key order, and therefore the new tree is bulk-loaded by
+    /// appending each entry to its rightmost leaf. Entries inserted or removed by other threads
+    /// during the scan may or may not be reflected in the new tree.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use scc::TreeIndex;
+    ///
+    /// let treeindex: TreeIndex<u64, u32> = TreeIndex::new();
+    ///
+    /// assert!(treeindex.insert(1, 10).is_ok());
+    /// assert!(treeindex.insert(2, 20).is_ok());
+    ///
+    /// let mapped = treeindex.map_values(|k, v| format!("{k}:{v}"));
+    /// assert_eq!(mapped.peek_with(&1, |_, v| v.clone()), Some("1:10".to_string()));
+    /// assert_eq!(mapped.peek_with(&2, |_, v| v.clone()), Some("2:20".to_string()));
+    /// ```
+    #[inline]
+    pub fn map_values<W: 'static + Clone, F: FnMut(&K, &V) -> W>(
+        &self,
+        mut f: F,
+    ) -> TreeIndex<K, W> {
+        let mapped = TreeIndex::new();
+        let guard = Guard::new();
+        for (k, v) in self.iter(&guard) {
+            let _result = mapped.insert_at_tail(k.clone(), f(k, v));
+        }
+        mapped
+    }
+
+    /// Splits the [`TreeIndex`] into two at the given key.
+    ///
+    /// Returns a new [`TreeIndex`] containing all the entries with keys greater than or equal to
+    /// the given key, and leaves the rest in `self`. Both trees are rebuilt from the partitioned
+    /// entries.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use scc::TreeIndex;
+    ///
+    /// let mut treeindex: TreeIndex<u64, u32> = TreeIndex::new();
+    ///
+    /// for k in 0..64 {
+    ///     assert!(treeindex.insert(k, 0).is_ok());
+    /// }
+    ///
+    /// let upper = treeindex.split_off(&40);
+    /// assert_eq!(treeindex.len(), 40);
+    /// assert_eq!(upper.len(), 24);
+    /// assert!(!treeindex.contains(&40));
+    /// assert!(upper.contains(&40));
+    /// ```
+    #[inline]
+    #[must_use]
+    pub fn split_off<Q>(&mut self, key: &Q) -> Self
+    where
+        K: Borrow<Q>,
+        Q: Ord + ?Sized,
+    {
+        let guard = Guard::new();
+        let lower = TreeIndex::new();
+        let upper = TreeIndex::new();
+        for (k, v) in self.iter(&guard) {
+            let target = if k.borrow() < key { &lower } else { &upper };
+            let _result = target.insert_at_tail(k.clone(), v.clone());
+        }
+        let new_root = lower.root.swap((None, Tag::None), Acquire).0;
+        self.num_replacements.fetch_add(1, Release);
+        if let Some(old_root) = self.root.swap((new_root, Tag::None), AcqRel).0 {
+            old_root.clear(&guard);
+        }
+        upper
+    }
+
+    /// Moves the value associated with `from` to a new key `to`.
+    ///
+    /// Returns `Ok(true)` if the value was moved, and `Ok(false)` if `from` does not exist. The
+    /// entry for `from` is removed before the new entry is inserted, therefore no readers can
+    /// observe both keys at the same time, whereas readers may observe neither of them while the
+    /// value is being moved.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error along with the supplied key if `to` exists.
+    ///
+    /// The move is not atomic: if another thread inserts `to` after the value is removed from
+    /// `from`, the value is put back to `from`, and if yet another entry has been inserted for
+    /// `from` in the meantime, the value is returned in the error since it cannot be stored
+    /// anywhere.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use scc::TreeIndex;
+    ///
+    /// let treeindex: TreeIndex<u64, u32> = TreeIndex::new();
+    ///
+    /// assert!(treeindex.insert(1, 10).is_ok());
+    /// assert!(treeindex.insert(2, 20).is_ok());
+    ///
+    /// assert_eq!(treeindex.rename(&1, 2), Err((2, None)));
+    /// assert_eq!(treeindex.rename(&1, 3), Ok(true));
+    /// assert_eq!(treeindex.rename(&1, 4), Ok(false));
+    /// assert_eq!(treeindex.peek_with(&3, |_, v| *v), Some(10));
+    /// assert!(!treeindex.contains(&1));
+    /// ```
+    #[inline]
+    pub fn rename(&self, from: &K, to: K) -> Result<bool, (K, Option<V>)> {
+        if self.contains(&to) {
+            return Err((to, None));
+        }
+        let mut val = None;
+        if !self.remove_if(from, |v| {
+            val.replace(v.clone());
+            true
+        }) {
+            return Ok(false);
+        }
+        let Some(val) = val else {
+            return Ok(false);
+        };
+        match self.insert(to, val) {
+            Ok(()) => Ok(true),
+            Err((to, val)) => {
+                // `to` was inserted in the meantime: put the value back.
+                match self.insert(from.clone(), val) {
+                    Ok(()) => Err((to, None)),
+                    Err((_, val)) => Err((to, Some(val))),
+                }
+            }
+        }
+    }
+
+    /// Returns a guarded reference to the value for the specified key without acquiring locks.
+    ///
+    /// Returns `None` if the key does not exist. The returned reference can survive as long as the
+    /// associated [`Guard`] is alive.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use scc::TreeIndex;
+    /// use scc::ebr::Guard;
+    ///
+    /// let treeindex: TreeIndex<u64, u32> = TreeIndex::new();
+    ///
+    /// let guard = Guard::new();
+    /// assert!(treeindex.peek(&1, &guard).is_none());
+    /// ```
+    #[inline]
+    pub fn peek<'g, Q>(&self, key: &Q, guard: &'g Guard) -> Option<&'g V>
+    where
+        K: Borrow<Q>,
+        Q: Ord + ?Sized,
+    {
+        if let Some(root_ref) = self.root.load(Acquire, guard).as_ref() {
+            return root_ref.search(key, guard);
+        }
+        None
+    }
+
+    /// Returns a clone of the value associated with the key along with the cost of looking it up.
+    ///
+    /// Averaging the [`DescentProfile`] over many lookups reveals how balanced the
+    /// [`TreeIndex`] is and how many times keys are compared; the cost is measured along a
+    /// separate descent, therefore this is much slower than [`TreeIndex::peek_with`].
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use scc::TreeIndex;
+    ///
+    /// let treeindex: TreeIndex<u64, u32> = TreeIndex::new();
+    ///
+    /// for k in 0..256 {
+    ///     assert!(treeindex.insert(k, 0).is_ok());
+    /// }
+    ///
+    /// let (val, profile) = treeindex.get_profiled(&7);
+    /// assert_eq!(val, Some(0));
+    /// assert_eq!(profile.levels, treeindex.depth());
+    /// assert!(profile.comparisons >= profile.levels);
+    /// ```
+    #[inline]
+    pub fn get_profiled<Q>(&self, key: &Q) -> (Option<V>, DescentProfile)
+    where
+        K: Borrow<Q>,
+        Q: Ord + ?Sized,
+    {
+        let guard = Guard::new();
+        let Some(root_ref) = self.root.load(Acquire, &guard).as_ref() else {
+            return (None, DescentProfile::default());
+        };
+        let (levels, comparisons) = root_ref.descent_profile(key, &guard);
+        (
+            root_ref.search(key, &guard).cloned(),
+            DescentProfile {
+                levels,
+                comparisons,
+            },
+        )
+    }
+
+    /// Peeks a key-value pair without acquiring locks.
+    ///
+    /// Returns `None` if the key does not exist.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use scc::TreeIndex;
+    ///
+    /// let treeindex: TreeIndex<u64, u32> = TreeIndex::new();
+    /// assert!(treeindex.peek_with(&1, |k, v| *v).is_none());
+    /// ```
+    #[inline]
+    pub fn peek_with<Q, R, F: FnOnce(&Q, &V) -> R>(&self, key: &Q, reader: F) -> Option<R>
+    where
+        K: Borrow<Q>,
+        Q: Ord + ?Sized,
+    {
+        let guard = Guard::new();
+        self.peek(key, &guard).map(|v| reader(key, v))
+    }
+
+    /// Returns `true` if the [`TreeIndex`] contains the key.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use scc::TreeIndex;
+    ///
+    /// let treeindex: TreeIndex<u64, u32> = TreeIndex::default();
+    ///
+    /// assert!(!treeindex.contains(&1));
+    /// assert!(treeindex.insert(1, 0).is_ok());
+    /// assert!(treeindex.contains(&1));
+    /// ```
+    #[inline]
+    pub fn contains<Q>(&self, key: &Q) -> bool
+    where
+        K: Borrow<Q>,
+        Q: Ord + ?Sized,
+    {
+        self.peek(key, &Guard::new()).is_some()
+    }
+
+    /// Returns the size of the [`TreeIndex`].
+    ///
+    /// It internally scans all the leaf nodes, and therefore the time complexity is O(N).
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use scc::TreeIndex;
+    ///
+    /// let treeindex: TreeIndex<u64, u32> = TreeIndex::new();
+    /// assert_eq!(treeindex.len(), 0);
+    /// ```
+    #[inline]
+    pub fn len(&self) -> usize {
+        let guard = Guard::new();
+        self.iter(&guard).count()
+    }
+
+    /// Counts the entries, and records the current [`Epoch`] and time along with the count.
+    ///
+    /// The count is not linearizable, since entries inserted or removed by other threads during
+    /// the scan may or may not be counted. Comparing two snapshots by their
+    /// [`CountSnapshot::delta`] and [`CountSnapshot::taken_at`] yields the rate of insertions and
+    /// removals.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use scc::TreeIndex;
+    /// use scc::ebr::Guard;
+    ///
+    /// let treeindex: TreeIndex<u64, u32> = TreeIndex::new();
+    ///
+    /// let guard = Guard::new();
+    /// assert_eq!(treeindex.count_snapshot(&guard).len, 0);
+    /// ```
+    #[inline]
+    pub fn count_snapshot(&self, guard: &Guard) -> CountSnapshot {
+        let epoch = guard.epoch();
+        let len = self.iter(guard).count();
+        CountSnapshot {
+            len,
+            epoch,
+            taken_at: Instant::now(),
+        }
+    }
+
+    /// Returns the minimum key, the maximum key, and the number of entries in a single scan.
+    ///
+    /// Returns `None` if the [`TreeIndex`] is empty.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use scc::TreeIndex;
+    ///
+    /// let treeindex: TreeIndex<u64, u32> = TreeIndex::new();
+    /// assert!(treeindex.summary().is_none());
+    ///
+    /// for k in 4..12 {
+    ///     assert!(treeindex.insert(k, 0).is_ok());
+    /// }
+    /// assert_eq!(treeindex.summary(), Some((4, 11, 8)));
+    /// ```
+    #[inline]
+    pub fn summary(&self) -> Option<(K, K, usize)> {
+        let guard = Guard::new();
+        let mut iter = self.iter(&guard);
+        let (min_key, _) = iter.next()?;
+        let (max_key, len) = iter.fold((min_key, 1), |(_, len), (k, _)| (k, len + 1));
+        Some((min_key.clone(), max_key.clone(), len))
+    }
+
+    /// Counts keys in `buckets` equal-width bins over the values that `projection` maps keys to.
+    ///
+    /// The bins evenly divide the interval between the minimum and maximum projected values, and
+    /// the last bin includes the maximum. Returns an empty [`Vec`] if `buckets` is `0`.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use scc::TreeIndex;
+    ///
+    /// let treeindex: TreeIndex<u64, u32> = TreeIndex::new();
+    ///
+    /// for k in 0..64 {
+    ///     assert!(treeindex.insert(k, 0).is_ok());
+    /// }
+    /// assert!(treeindex.insert(1000, 0).is_ok());
+    ///
+    /// let histogram = treeindex.key_histogram(4, |k| *k as f64);
+    /// assert_eq!(histogram, vec![64, 0, 0, 1]);
+    /// ```
+    #[inline]
+    #[allow(
+        clippy::cast_possible_truncation,
+        clippy::cast_precision_loss,
+        clippy::cast_sign_loss
+    )]
+    pub fn key_histogram<F: Fn(&K) -> f64>(&self, buckets: usize, projection: F) -> Vec<usize> {
+        let mut histogram = vec![0; buckets];
+        if buckets == 0 {
+            return histogram;
+        }
+        let guard = Guard::new();
+        let projected = self
+            .iter(&guard)
+            .map(|(k, _)| projection(k))
+            .collect::<Vec<_>>();
+        let (min, max) = projected
+            .iter()
+            .fold((f64::INFINITY, f64::NEG_INFINITY), |(min, max), p| {
+                (min.min(*p), max.max(*p))
+            });
+        let width = (max - min) / buckets as f64;
+        for p in projected {
+            let bucket = if width > 0.0 {
+                (((p - min) / width) as usize).min(buckets - 1)
+            } else {
+                0
+            };
+            histogram[bucket] += 1;
+        }
+        histogram
+    }
+
+    /// Estimates the fraction of entries contained in the range.
+    ///
+    /// Entries are only counted in the leaf nodes overlapping the boundaries of the range, and the
+    /// number of entries in any other leaf node is estimated from the number of entries in its
+    /// first leaf. The returned value is in `[0.0, 1.0]`.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use scc::TreeIndex;
+    ///
+    /// let treeindex: TreeIndex<u64, u32> = TreeIndex::new();
+    /// assert_eq!(treeindex.range_selectivity(..), 0.0);
+    ///
+    /// for k in 0..1024 {
+    ///     assert!(treeindex.insert(k, 0).is_ok());
+    /// }
+    ///
+    /// assert_eq!(treeindex.range_selectivity(..), 1.0);
+    /// assert!((treeindex.range_selectivity(..512) - 0.5).abs() < 0.1);
+    /// ```
+    #[inline]
+    pub fn range_selectivity<R: RangeBounds<K>>(&self, range: R) -> f64 {
+        let guard = Guard::new();
+        self.root
+            .load(Acquire, &guard)
+            .as_ref()
+            .map_or(0.0, |root_ref| {
+                let (num_contained, num_entries) = root_ref.estimate(&range, &guard);
+                if num_entries == 0.0 {
+                    0.0
+                } else {
+                    num_contained / num_entries
+                }
+            })
+    }
+
+    /// Returns the keys in `expected` that are not in the [`TreeIndex`].
+    ///
+    /// `expected` is assumed to be sorted in ascending order; it is merge-joined with the keys in
+    /// the range from the first to the last expected key in a single pass.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use scc::TreeIndex;
+    ///
+    /// let treeindex: TreeIndex<u64, u32> = TreeIndex::new();
+    ///
+    /// for k in [1, 3, 5] {
+    ///     assert!(treeindex.insert(k, 0).is_ok());
+    /// }
+    ///
+    /// assert_eq!(treeindex.missing_keys(&[0, 1, 2, 3, 6]), vec![0, 2, 6]);
+    /// assert!(treeindex.missing_keys(&[]).is_empty());
+    /// ```
+    #[inline]
+    pub fn missing_keys(&self, expected: &[K]) -> Vec<K> {
+        let (Some(first), Some(last)) = (expected.first(), expected.last()) else {
+            return Vec::new();
+        };
+        let guard = Guard::new();
+        let mut keys = self
+            .range((Included(first), Included(last)), &guard)
+            .map(|(k, _)| k)
+            .peekable();
+        let mut missing = Vec::new();
+        for key in expected {
+            while keys.next_if(|k| *k < key).is_some() {}
+            if keys.peek() != Some(&key) {
+                missing.push(key.clone());
+            }
+        }
+        missing
+    }
+
+    /// Returns the smallest key equal to or greater than `start` that is not in the
+    /// [`TreeIndex`].
+    ///
+    /// The successor of a key is computed by adding `1` to it. It scans the keys from `start`
+    /// until a missing successor is found, and therefore the time complexity is O(N). The
+    /// addition overflows if all the keys from `start` up to the maximum value exist.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use scc::TreeIndex;
+    ///
+    /// let treeindex: TreeIndex<u64, u32> = TreeIndex::new();
+    /// assert_eq!(treeindex.first_gap(0), 0);
+    ///
+    /// for k in 0..8 {
+    ///     assert!(treeindex.insert(k, 0).is_ok());
+    /// }
+    /// assert!(treeindex.remove(&5));
+    ///
+    /// assert_eq!(treeindex.first_gap(0), 5);
+    /// assert_eq!(treeindex.first_gap(6), 8);
+    /// ```
+    #[inline]
+    pub fn first_gap(&self, start: K) -> K
+    where
+        K: Add<Output = K> + From<u8>,
+    {
+        let guard = Guard::new();
+        let mut candidate = start.clone();
+        for (k, _) in self.range(start.., &guard) {
+            if *k != candidate {
+                break;
+            }
+            candidate = candidate + K::from(1);
+        }
+        candidate
+    }
+
+    /// Inserts the value at the smallest key equal to or greater than `start` that is not in the
+    /// [`TreeIndex`], and returns the key.
+    ///
+    /// If another thread inserts the key in the meantime, it searches for the next missing key
+    /// from the key, therefore concurrent claims never return the same key. The successor of a
+    /// key is computed by adding `1` to it as in [`TreeIndex::first_gap`].
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use scc::TreeIndex;
+    ///
+    /// let treeindex: TreeIndex<u64, u32> = TreeIndex::new();
+    ///
+    /// assert_eq!(treeindex.claim_first_gap(0, 10), 0);
+    /// assert_eq!(treeindex.claim_first_gap(0, 11), 1);
+    /// assert!(treeindex.remove(&0));
+    /// assert_eq!(treeindex.claim_first_gap(0, 12), 0);
+    /// assert_eq!(treeindex.peek_with(&0, |_, v| *v), Some(12));
+    /// ```
+    #[inline]
+    pub fn claim_first_gap(&self, start: K, val: V) -> K
+    where
+        K: Add<Output = K> + From<u8>,
+    {
+        let mut start = start;
+        let mut val = val;
+        loop {
+            let key = self.first_gap(start);
+            match self.insert(key.clone(), val) {
+                Ok(()) => return key,
+                // The key was claimed in the meantime.
+                Err((k, v)) => {
+                    start = k;
+                    val = v;
+                }
+            }
+        }
+    }
+
+    /// Returns `true` if the [`TreeIndex`] is empty.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use scc::TreeIndex;
+    ///
+    /// let treeindex: TreeIndex<u64, u32> = TreeIndex::new();
+    ///
+    /// assert!(treeindex.is_empty());
+    /// ```
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        let guard = Guard::new();
+        !self.iter(&guard).any(|_| true)
+    }
+
+    /// Returns an [`Iter`].
+    ///
+    /// The returned [`Iter`] starts scanning from the minimum key-value pair. Key-value pairs
+    /// are scanned in ascending order, and key-value pairs that have existed since the invocation
+    /// of the method are guaranteed to be visited if they are not removed. However, it is possible
+    /// to visit removed key-value pairs momentarily.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use scc::TreeIndex;
+    /// use scc::ebr::Guard;
+    ///
+    /// let treeindex: TreeIndex<u64, u32> = TreeIndex::new();
+    ///
+    /// let guard = Guard::new();
+    /// let mut iter = treeindex.iter(&guard);
+    /// assert!(iter.next().is_none());
+    /// ```
+    #[inline]
+    pub fn iter<'t, 'g>(&'t self, guard: &'g Guard) -> Iter<'t, 'g, K, V> {
+        Iter::new(self, guard)
+    }
+
+    /// Folds every entry into an accumulator in ascending key order.
+    ///
+    /// The same guarantees as [`TreeIndex::iter`] apply.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use scc::TreeIndex;
+    ///
+    /// let treeindex: TreeIndex<u64, u32> = TreeIndex::new();
+    ///
+    /// for k in 1..=4 {
+    ///     assert!(treeindex.insert(k, k as u32 * 10).is_ok());
+    /// }
+    ///
+    /// assert_eq!(treeindex.fold(0, |acc, _, v| acc + v), 100);
+    /// assert_eq!(treeindex.fold(0, |acc, k, _| acc * 10 + k), 1234);
+    /// ```
+    #[inline]
+    pub fn fold<B, F: FnMut(B, &K, &V) -> B>(&self, init: B, mut f: F) -> B {
+        let guard = Guard::new();
+        self.iter(&guard).fold(init, |acc, (k, v)| f(acc, k, v))
+    }
+
+    /// Invokes the closure on every entry in ascending key order until the closure returns an
+    /// error.
+    ///
+    /// # Errors
+    ///
+    /// Returns the first error returned by the closure.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use scc::TreeIndex;
+    ///
+    /// let treeindex: TreeIndex<u64, u32> = TreeIndex::new();
+    ///
+    /// for k in 0..64 {
+    ///     assert!(treeindex.insert(k, 0).is_ok());
+    /// }
+    ///
+    /// let mut num_visited = 0;
+    /// let result = treeindex.try_for_each(|k, _| {
+    ///     num_visited += 1;
+    ///     if *k == 8 { Err(*k) } else { Ok(()) }
+    /// });
+    /// assert_eq!(result, Err(8));
+    /// assert_eq!(num_visited, 9);
+    /// ```
+    #[inline]
+    pub fn try_for_each<E, F: FnMut(&K, &V) -> Result<(), E>>(&self, mut f: F) -> Result<(), E> {
+        let guard = Guard::new();
+        self.iter(&guard).try_for_each(|(k, v)| f(k, v))
+    }
+
+    /// Passes clones of the entries to the supplied asynchronous closure in ascending key order.
+    ///
+    /// The scan stops when the [`Future`](std::future::Future) returned by the closure resolves to
+    /// `false`; `true` is returned if all the entries were passed. No [`Guard`] is held while the
+    /// closure is awaited, and the scan resumes after the last key passed, therefore sending
+    /// entries through a bounded channel does not block other tasks, and the channel exerts
+    /// backpressure on the scan. Resuming the scan costs O(log N) for each entry.
+    ///
+    /// It is an asynchronous method returning an `impl Future` for the caller to await.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use scc::TreeIndex;
+    ///
+    /// let treeindex: TreeIndex<u64, u32> = TreeIndex::new();
+    ///
+    /// for k in 0..64 {
+    ///     assert!(treeindex.insert(k, 0).is_ok());
+    /// }
+    ///
+    /// let mut keys = Vec::new();
+    /// let future_for_each = treeindex.for_each_async(|k, _| {
+    ///     keys.push(k);
+    ///     async move { k < 7 }
+    /// });
+    /// assert!(!futures::executor::block_on(future_for_each));
+    /// assert_eq!(keys, (0..8).collect::<Vec<_>>());
+    /// ```
+    #[inline]
+    pub async fn for_each_async<F, Fut>(&self, mut f: F) -> bool
+    where
+        F: FnMut(K, V) -> Fut,
+        Fut: std::future::Future<Output = bool>,
+    {
+        let mut last_key: Option<K> = None;
+        loop {
+            let Some((k, v)) = self.next_entry_after(last_key.as_ref()) else {
+                return true;
+            };
+            last_key.replace(k.clone());
+            if !f(k, v).await {
+                return false;
+            }
+        }
+    }
+
+    /// Returns a [`Stream`] of clones of the entries in ascending key order.
+    ///
+    /// The entries of a leaf are cloned together when the [`Stream`] reaches the leaf, and no
+    /// [`Guard`] is held in between, therefore a slow consumer does not prevent retired memory
+    /// from being reclaimed; resuming the scan costs O(log N) for each leaf. Entries inserted or
+    /// removed while the [`Stream`] is consumed may or may not be yielded.
+    ///
+    /// It requires the `stream` feature.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use futures::executor::block_on;
+    /// use futures::StreamExt;
+    /// use scc::TreeIndex;
+    ///
+    /// let treeindex: TreeIndex<u64, u32> = TreeIndex::new();
+    ///
+    /// for k in (0..64).rev() {
+    ///     assert!(treeindex.insert(k, 0).is_ok());
+    /// }
+    ///
+    /// let entries = block_on(treeindex.stream().take(8).collect::<Vec<_>>());
+    /// assert_eq!(entries, (0..8).map(|k| (k, 0)).collect::<Vec<_>>());
+    /// ```
+    #[cfg(feature = "stream")]
+    #[inline]
+    pub fn stream(&self) -> Stream<'_, K, V> {
+        Stream {
+            tree: self,
+            entries: VecDeque::new(),
+            last_key: None,
+        }
+    }
+
+    /// Returns a [`Range`] that scans keys in the given range.
+    ///
+    /// Key-value pairs in the range are scanned in ascending order, and key-value pairs that have
+    /// existed since the invocation of the method are guaranteed to be visited if they are not
+    /// removed. However, it is possible to visit removed key-value pairs momentarily.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use scc::TreeIndex;
+    /// use scc::ebr::Guard;
+    ///
+    /// let treeindex: TreeIndex<u64, u32> = TreeIndex::new();
+    ///
+    /// let guard = Guard::new();
+    /// assert_eq!(treeindex.range(4..=8, &guard).count(), 0);
+    /// ```
+    #[inline]
+    pub fn range<'t, 'g, R: RangeBounds<K>>(
+        &'t self,
+        range: R,
+        guard: &'g Guard,
+    ) -> Range<'t, 'g, K, V, R> {
+        Range::new(&self.root, range, guard)
+    }
+
+    /// Returns an [`Iterator`] over the entries in the union of the given ranges in ascending key
+    /// order.
+    ///
+    /// Ranges are sorted by their start bounds, and each range is scanned from the last key that
+    /// the preceding ranges yielded, so that overlapping parts are scanned only once. The same
+    /// guarantees as [`TreeIndex::range`] apply to each range; all of them are scanned under the
+    /// same [`Guard`], however the result is not a snapshot of the [`TreeIndex`].
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use scc::TreeIndex;
+    /// use scc::ebr::Guard;
+    ///
+    /// let treeindex: TreeIndex<u64, u32> = TreeIndex::new();
+    ///
+    /// for k in 0..16 {
+    ///     assert!(treeindex.insert(k, 0).is_ok());
+    /// }
+    ///
+    /// let guard = Guard::new();
+    /// let keys = treeindex
+    ///     .multi_range([10..12, 2..4, 3..6], &guard)
+    ///     .map(|(k, _)| *k);
+    /// assert_eq!(keys.collect::<Vec<_>>(), vec![2, 3, 4, 5, 10, 11]);
+    /// ```
+    #[inline]
+    pub fn multi_range<'t, 'g, R: RangeBounds<K>, I: IntoIterator<Item = R>>(
+        &'t self,
+        ranges: I,
+        guard: &'g Guard,
+    ) -> impl Iterator<Item = (&'g K, &'g V)> + 't
+    where
+        'g: 't,
+    {
+        let mut ranges = ranges
+            .into_iter()
+            .map(|r| (r.start_bound().cloned(), r.end_bound().cloned()))
+            .collect::<Vec<_>>();
+        ranges.sort_by(|(a, _), (b, _)| match (a, b) {
+            (Unbounded, Unbounded) => Ordering::Equal,
+            (Unbounded, _) => Ordering::Less,
+            (_, Unbounded) => Ordering::Greater,
+            (Included(x) | Excluded(x), Included(y) | Excluded(y)) => x
+                .cmp(y)
+                .then_with(|| matches!(a, Excluded(_)).cmp(&matches!(b, Excluded(_)))),
+        });
+
+        let mut ranges = ranges.into_iter();
+        let mut current = None::<Range<'t, 'g, K, V, _>>;
+        let mut last_key: Option<&'g K> = None;
+        std::iter::from_fn(move || loop {
+            if let Some(entry) = current.as_mut().and_then(Iterator::next) {
+                last_key.replace(entry.0);
+                return Some(entry);
+            }
+            let (start, end) = ranges.next()?;
+            let start = match (start, last_key) {
+                (Included(s), Some(last)) if s <= *last => Excluded(last.clone()),
+                (Excluded(s), Some(last)) if s < *last => Excluded(last.clone()),
+                (Unbounded, Some(last)) => Excluded(last.clone()),
+                (start, _) => start,
+            };
+            current.replace(self.range((start, end), guard));
+        })
+    }
+
+    /// Returns an [`Iterator`] over the entries that satisfy the given predicate in ascending key
+    /// order.
+    ///
+    /// All the entries are scanned, therefore it is a linear time operation. The same guarantees
+    /// as [`TreeIndex::iter`] apply.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use scc::TreeIndex;
+    /// use scc::ebr::Guard;
+    ///
+    /// let treeindex: TreeIndex<u64, u32> = TreeIndex::new();
+    ///
+    /// for k in 0..8 {
+    ///     assert!(treeindex.insert(k, k as u32 % 3).is_ok());
+    /// }
+    ///
+    /// let guard = Guard::new();
+    /// let keys = treeindex.filter_scan(|_, v| *v == 0, &guard).map(|(k, _)| *k);
+    /// assert_eq!(keys.collect::<Vec<_>>(), vec![0, 3, 6]);
+    /// ```
+    #[inline]
+    pub fn filter_scan<'t, 'g, F: Fn(&K, &V) -> bool>(
+        &'t self,
+        f: F,
+        guard: &'g Guard,
+    ) -> std::iter::Filter<Iter<'t, 'g, K, V>, impl FnMut(&(&'g K, &'g V)) -> bool> {
+        self.iter(guard).filter(move |(k, v)| f(k, v))
+    }
+
+    /// Returns an [`Iterator`] over keys along with the results of resolving their values in
+    /// ascending key order.
+    ///
+    /// `resolve` is invoked on each value only when the [`Iterator`] reaches the entry, which is
+    /// useful when values are handles to data stored elsewhere. The same guarantees as
+    /// [`TreeIndex::iter`] apply.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use scc::TreeIndex;
+    /// use scc::ebr::Guard;
+    ///
+    /// let storage = ["zero", "one", "two"];
+    /// let treeindex: TreeIndex<u64, usize> = TreeIndex::new();
+    ///
+    /// assert!(treeindex.insert(20, 2).is_ok());
+    /// assert!(treeindex.insert(10, 1).is_ok());
+    ///
+    /// let guard = Guard::new();
+    /// let mut iter = treeindex.resolve_iter(|offset| storage[*offset], &guard);
+    /// assert_eq!(iter.next(), Some((&10, "one")));
+    /// assert_eq!(iter.next(), Some((&20, "two")));
+    /// assert!(iter.next().is_none());
+    /// ```
+    #[inline]
+    #[allow(clippy::type_complexity)]
+    pub fn resolve_iter<'t, 'g, R, F: Fn(&V) -> R>(
+        &'t self,
+        resolve: F,
+        guard: &'g Guard,
+    ) -> std::iter::Map<Iter<'t, 'g, K, V>, impl FnMut((&'g K, &'g V)) -> (&'g K, R)> {
+        self.iter(guard).map(move |(k, v)| (k, resolve(v)))
+    }
+
+    /// Returns an [`Iterator`] over distinct values in the order they first appear in ascending
+    /// key order.
+    ///
+    /// The values that have been returned are kept in a [`BTreeSet`] until the
+    /// [`Iterator`] is dropped.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use scc::TreeIndex;
+    /// use scc::ebr::Guard;
+    ///
+    /// let treeindex: TreeIndex<u64, u32> = TreeIndex::new();
+    ///
+    /// for (k, v) in [(1, 30), (2, 10), (3, 30), (4, 20), (5, 10)] {
+    ///     assert!(treeindex.insert(k, v).is_ok());
+    /// }
+    ///
+    /// let guard = Guard::new();
+    /// let values = treeindex.distinct_values(&guard).copied().collect::<Vec<_>>();
+    /// assert_eq!(values, vec![30, 10, 20]);
+    /// ```
+    #[inline]
+    #[allow(clippy::type_complexity)]
+    pub fn distinct_values<'t, 'g>(
+        &'t self,
+        guard: &'g Guard,
+    ) -> std::iter::FilterMap<Iter<'t, 'g, K, V>, impl FnMut((&'g K, &'g V)) -> Option<&'g V>>
+    where
+        V: Ord,
+    {
+        let mut seen = BTreeSet::new();
+        self.iter(guard)
+            .filter_map(move |(_, v)| seen.insert(v).then_some(v))
+    }
+
+    /// Returns an [`Iterator`] over the entries along with their preceding and succeeding
+    /// entries in ascending key order.
+    ///
+    /// The first entry has no preceding entry, and the last entry has no succeeding entry.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use scc::TreeIndex;
+    /// use scc::ebr::Guard;
+    ///
+    /// let treeindex: TreeIndex<u64, u32> = TreeIndex::new();
+    ///
+    /// for k in 1..4 {
+    ///     assert!(treeindex.insert(k, 0).is_ok());
+    /// }
+    ///
+    /// let guard = Guard::new();
+    /// let mut iter = treeindex.windows3(&guard);
+    /// assert_eq!(iter.next(), Some((None, (&1, &0), Some((&2, &0)))));
+    /// assert_eq!(iter.next(), Some((Some((&1, &0)), (&2, &0), Some((&3, &0)))));
+    /// assert_eq!(iter.next(), Some((Some((&2, &0)), (&3, &0), None)));
+    /// assert_eq!(iter.next(), None);
+    /// ```
+    #[inline]
+    #[allow(clippy::type_complexity)]
+    pub fn windows3<'t, 'g>(
+        &'t self,
+        guard: &'g Guard,
+    ) -> impl Iterator<
+        Item = (
+            Option<(&'g K, &'g V)>,
+            (&'g K, &'g V),
+            Option<(&'g K, &'g V)>,
+        ),
+    > + 't
+    where
+        'g: 't,
+    {
+        let mut iter = self.iter(guard).peekable();
+        let mut prev = None;
+        std::iter::from_fn(move || {
+            let curr = iter.next()?;
+            let window = (prev, curr, iter.peek().copied());
+            prev.replace(curr);
+            Some(window)
+        })
+    }
+
+    /// Returns a [`Range`] that scans keys from the minimum key up to the given key.
+    ///
+    /// `end` is included if `include_end` is `true`. The scan stops at the first key beyond `end`
+    /// without visiting the remaining leaves.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use scc::TreeIndex;
+    /// use scc::ebr::Guard;
+    ///
+    /// let treeindex: TreeIndex<u64, u32> = TreeIndex::new();
+    ///
+    /// for k in 0..64 {
+    ///     assert!(treeindex.insert(k, 0).is_ok());
+    /// }
+    ///
+    /// let guard = Guard::new();
+    /// assert_eq!(treeindex.iter_until(&8, false, &guard).count(), 8);
+    /// assert_eq!(treeindex.iter_until(&8, true, &guard).count(), 9);
+    /// ```
+    #[inline]
+    pub fn iter_until<'t, 'g>(
+        &'t self,
+        end: &K,
+        include_end: bool,
+        guard: &'g Guard,
+    ) -> Range<'t, 'g, K, V, (Bound<K>, Bound<K>)> {
+        let end_bound = if include_end {
+            Included(end.clone())
+        } else {
+            Excluded(end.clone())
+        };
+        self.range((Unbounded, end_bound), guard)
+    }
+
+    /// Returns a [`Range`] that scans keys greater than the given key.
+    ///
+    /// This resumes a scan after the last key visited, e.g., for pagination.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use scc::TreeIndex;
+    /// use scc::ebr::Guard;
+    ///
+    /// let treeindex: TreeIndex<u64, u32> = TreeIndex::new();
+    ///
+    /// for k in 0..64 {
+    ///     assert!(treeindex.insert(k, 0).is_ok());
+    /// }
+    ///
+    /// let guard = Guard::new();
+    /// assert_eq!(treeindex.scan_after(&8, &guard).next(), Some((&9, &0)));
+    /// assert_eq!(treeindex.scan_after(&8, &guard).count(), 55);
+    /// ```
+    #[inline]
+    pub fn scan_after<'t, 'g>(
+        &'t self,
+        key: &K,
+        guard: &'g Guard,
+    ) -> Range<'t, 'g, K, V, (Bound<K>, Bound<K>)> {
+        self.range((Excluded(key.clone()), Unbounded), guard)
+    }
+
+    /// Returns a [`Range`] over the entries of the child of the root that the separator key
+    /// bounds.
+    ///
+    /// `separator` is one of the separator keys of the root that [`TreeIndex::bfs_nodes`] reports,
+    /// or `None` for the child after the last separator. The [`Range`] covers the keys greater
+    /// than the preceding separator and not greater than `separator`, therefore iterating over all
+    /// the children of the root in order yields all the entries in ascending key order, and each
+    /// of them can be handed over to a different thread.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use scc::TreeIndex;
+    /// use scc::ebr::Guard;
+    ///
+    /// let treeindex: TreeIndex<u64, u32> = TreeIndex::new();
+    ///
+    /// for k in 0..256 {
+    ///     assert!(treeindex.insert(k, 0).is_ok());
+    /// }
+    ///
+    /// let guard = Guard::new();
+    /// let separators = treeindex.bfs_nodes(&guard).next().unwrap().separators;
+    /// let num_entries = separators
+    ///     .into_iter()
+    ///     .map(Some)
+    ///     .chain([None])
+    ///     .map(|separator| treeindex.subtree_iter(separator, &guard).count())
+    ///     .sum::<usize>();
+    /// assert_eq!(num_entries, 256);
+    /// ```
+    #[inline]
+    pub fn subtree_iter<'t, 'g>(
+        &'t self,
+        separator: Option<&K>,
+        guard: &'g Guard,
+    ) -> Range<'t, 'g, K, V, (Bound<K>, Bound<K>)> {
+        let separators = self
+            .root
+            .load(Acquire, guard)
+            .as_ref()
+            .map_or_else(Vec::new, |root_ref| {
+                root_ref.children(&mut Vec::new(), guard).0
+            });
+        let start_bound = separators
+            .into_iter()
+            .take_while(|s| separator.map_or(true, |separator| *s < separator))
+            .last()
+            .map_or(Unbounded, |s| Excluded(s.clone()));
+        let end_bound = separator.map_or(Unbounded, |separator| Included(separator.clone()));
+        self.range((start_bound, end_bound), guard)
+    }
+
+    /// Returns an [`Iterator`] over entries along with the child indexes from the root to the
+    /// leaf containing each entry.
+    ///
+    /// The length of the path of an entry is equal to the depth of the [`TreeIndex`]. The paths
+    /// of the leaves are collected when the [`Iterator`] is created, and the entries of a leaf are
+    /// read when the [`Iterator`] reaches the leaf, sharing the path of the leaf.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use scc::TreeIndex;
+    /// use scc::ebr::Guard;
+    ///
+    /// let treeindex: TreeIndex<u64, u32> = TreeIndex::new();
+    ///
+    /// assert!(treeindex.insert(1, 10).is_ok());
+    ///
+    /// let guard = Guard::new();
+    /// let mut iter = treeindex.iter_with_path(&guard);
+    /// let (k, v, path) = iter.next().unwrap();
+    /// assert_eq!((k, v, &path[..]), (&1, &10, &[0][..]));
+    /// assert!(iter.next().is_none());
+    /// ```
+    #[inline]
+    pub fn iter_with_path<'g>(
+        &self,
+        guard: &'g Guard,
+    ) -> impl Iterator<Item = (&'g K, &'g V, Rc<[usize]>)> {
+        let mut leaves = Vec::new();
+        if let Some(root_ref) = self.root.load(Acquire, guard).as_ref() {
+            root_ref.for_each_leaf(
+                &mut Vec::new(),
+                &mut |path, leaf| leaves.push((Rc::<[usize]>::from(path), leaf)),
+                guard,
+            );
+        }
+        leaves
+            .into_iter()
+            .flat_map(|(path, leaf)| Scanner::new(leaf).map(move |(k, v)| (k, v, path.clone())))
+    }
+
+    /// Returns an [`Iterator`] over the occupancy statistics of leaves in ascending key order.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use scc::TreeIndex;
+    /// use scc::ebr::Guard;
+    ///
+    /// let treeindex: TreeIndex<u64, u32> = TreeIndex::new();
+    ///
+    /// for k in 0..64 {
+    ///     assert!(treeindex.insert(k, 0).is_ok());
+    /// }
+    ///
+    /// let guard = Guard::new();
+    /// let report = treeindex.leaf_report(&guard).collect::<Vec<_>>();
+    /// assert!(report.len() > 1);
+    /// assert_eq!(report[0].min_key, Some(&0));
+    /// assert_eq!(report.iter().map(|info| info.len).sum::<usize>(), 64);
+    /// ```
+    #[inline]
+    pub fn leaf_report<'g>(&self, guard: &'g Guard) -> impl Iterator<Item = LeafInfo<'g, K>> {
+        let first_leaf = self
+            .root
+            .load(Acquire, guard)
+            .as_ref()
+            .and_then(|root_ref| root_ref.min(guard))
+            .map(|scanner| scanner.leaf());
+        std::iter::successors(first_leaf, move |leaf| {
+            leaf.next_ptr(Acquire, guard).as_ref()
+        })
+        .map(|leaf| {
+            let mut info = LeafInfo {
+                min_key: None,
+                max_key: None,
+                len: 0,
+                capacity: leaf::DIMENSION.num_entries,
+            };
+            for (k, _) in Scanner::new(leaf) {
+                info.min_key.get_or_insert(k);
+                info.max_key.replace(k);
+                info.len += 1;
+            }
+            info
+        })
+    }
+
+    /// Checks if the [`TreeIndex`] has exactly the same internal structure as the other one.
+    ///
+    /// Unlike [`PartialEq`], two trees are structurally equal only if they have the same nodes at
+    /// each level with the same separator keys, and the same entries in each leaf. The result may
+    /// be inaccurate if either tree is concurrently modified.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use scc::TreeIndex;
+    /// use scc::ebr::Guard;
+    ///
+    /// let ascending: TreeIndex<u64, u32> = TreeIndex::new();
+    /// let descending: TreeIndex<u64, u32> = TreeIndex::new();
+    ///
+    /// for k in 0..64 {
+    ///     assert!(ascending.insert(k, 0).is_ok());
+    ///     assert!(descending.insert(63 - k, 0).is_ok());
+    /// }
+    ///
+    /// let guard = Guard::new();
+    /// assert!(ascending == descending);
+    /// assert!(ascending.structurally_eq(&ascending, &guard));
+    /// assert!(!ascending.structurally_eq(&descending, &guard));
+    /// ```
+    #[inline]
+    pub fn structurally_eq(&self, other: &Self, guard: &Guard) -> bool
+    where
+        V: PartialEq,
+    {
+        match (
+            self.root.load(Acquire, guard).as_ref(),
+            other.root.load(Acquire, guard).as_ref(),
+        ) {
+            (Some(root_ref), Some(other_root_ref)) => {
+                root_ref.structurally_eq(other_root_ref, guard)
+            }
+            (None, None) => true,
+            _ => false,
+        }
+    }
+
+    /// Returns an [`Iterator`] over the differences from `self` to `other` in ascending key
+    /// order.
+    ///
+    /// The two trees are scanned side by side in a single merge-join pass, and entries that are
+    /// equal in both are skipped.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use scc::TreeIndex;
+    /// use scc::ebr::Guard;
+    /// use scc::tree_index::Diff;
+    ///
+    /// let old: TreeIndex<u64, u32> = TreeIndex::new();
+    /// let new: TreeIndex<u64, u32> = TreeIndex::new();
+    ///
+    /// assert!(old.insert(1, 10).is_ok());
+    /// assert!(old.insert(2, 20).is_ok());
+    /// assert!(old.insert(3, 30).is_ok());
+    /// assert!(new.insert(2, 21).is_ok());
+    /// assert!(new.insert(3, 30).is_ok());
+    /// assert!(new.insert(4, 40).is_ok());
+    ///
+    /// let guard = Guard::new();
+    /// assert_eq!(
+    ///     old.diff(&new, &guard).collect::<Vec<_>>(),
+    ///     vec![
+    ///         Diff::Removed(&1, &10),
+    ///         Diff::Changed(&2, &20, &21),
+    ///         Diff::Added(&4, &40),
+    ///     ]
+    /// );
+    /// ```
+    #[inline]
+    pub fn diff<'t, 'g>(
+        &'t self,
+        other: &'t Self,
+        guard: &'g Guard,
+    ) -> impl Iterator<Item = Diff<'g, K, V>> + 't
+    where
+        'g: 't,
+        V: PartialEq,
+    {
+        let mut old = self.iter(guard).peekable();
+        let mut new = other.iter(guard).peekable();
+        std::iter::from_fn(move || loop {
+            let diff = match (old.peek(), new.peek()) {
+                (Some((old_key, _)), Some((new_key, _))) => match old_key.cmp(new_key) {
+                    Ordering::Less => old.next().map(|(k, v)| Diff::Removed(k, v)),
+                    Ordering::Greater => new.next().map(|(k, v)| Diff::Added(k, v)),
+                    Ordering::Equal => {
+                        let ((k, old_val), (_, new_val)) = (old.next()?, new.next()?);
+                        if old_val == new_val {
+                            continue;
+                        }
+                        Some(Diff::Changed(k, old_val, new_val))
+                    }
+                },
+                (Some(_), None) => old.next().map(|(k, v)| Diff::Removed(k, v)),
+                (None, Some(_)) => new.next().map(|(k, v)| Diff::Added(k, v)),
+                (None, None) => None,
+            };
+            return diff;
+        })
+    }
+
+    /// Returns an [`Iterator`] over the nodes of the [`TreeIndex`] in breadth-first order.
+    ///
+    /// The root is visited first, and then nodes are visited level by level in ascending key
+    /// order. This is a diagnostic method, and the result may be inconsistent if the
+    /// [`TreeIndex`] is concurrently modified.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use scc::TreeIndex;
+    /// use scc::ebr::Guard;
+    ///
+    /// let treeindex: TreeIndex<u64, u32> = TreeIndex::new();
+    ///
+    /// for k in 0..64 {
+    ///     assert!(treeindex.insert(k, 0).is_ok());
+    /// }
+    ///
+    /// let guard = Guard::new();
+    /// let root = treeindex.bfs_nodes(&guard).next().unwrap();
+    /// assert_eq!(root.level, 0);
+    /// assert_eq!(root.num_children, root.separators.len() + 1);
+    /// ```
+    #[inline]
+    pub fn bfs_nodes<'g>(&self, guard: &'g Guard) -> impl Iterator<Item = NodeInfo<'g, K>> {
+        let mut queue = VecDeque::new();
+        if let Some(root_ref) = self.root.load(Acquire, guard).as_ref() {
+            queue.push_back((root_ref, 0));
+        }
+        let mut child_nodes = Vec::new();
+        std::iter::from_fn(move || {
+            let (node, level) = queue.pop_front()?;
+            let (separators, num_children) = node.children(&mut child_nodes, guard);
+            queue.extend(child_nodes.drain(..).map(|child| (child, level + 1)));
+            Some(NodeInfo {
+                level,
+                is_leaf_node: matches!(node, Node::Leaf(_)),
+                num_children,
+                separators,
+            })
+        })
+    }
+
+    /// Returns the number of leaves containing entries.
+    ///
+    /// Leaves whose entries have all been removed are not counted even if they are still linked
+    /// in the tree.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use scc::TreeIndex;
+    ///
+    /// let treeindex: TreeIndex<u64, u32> = TreeIndex::new();
+    /// assert_eq!(treeindex.leaf_count(), 0);
+    ///
+    /// assert!(treeindex.insert(1, 10).is_ok());
+    /// assert_eq!(treeindex.leaf_count(), 1);
+    /// ```
+    #[inline]
+    pub fn leaf_count(&self) -> usize {
+        let guard = Guard::new();
+        let mut num_leaves = 0;
+        if let Some(root_ref) = self.root.load(Acquire, &guard).as_ref() {
+            root_ref.for_each_leaf(
+                &mut Vec::new(),
+                &mut |_, leaf| {
+                    if !leaf.is_empty() {
+                        num_leaves += 1;
+                    }
+                },
+                &guard,
+            );
+        }
+        num_leaves
+    }
+
+    /// Returns the number of nodes above leaves.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use scc::TreeIndex;
+    ///
+    /// let treeindex: TreeIndex<u64, u32> = TreeIndex::new();
+    /// assert_eq!(treeindex.internal_node_count(), 0);
+    ///
+    /// assert!(treeindex.insert(1, 10).is_ok());
+    /// assert_eq!(treeindex.internal_node_count(), 1);
+    /// ```
+    #[inline]
+    pub fn internal_node_count(&self) -> usize {
+        let guard = Guard::new();
+        self.bfs_nodes(&guard).count()
+    }
+
+    /// Returns an [`Iterator`] over all the initialized entries in the order they are physically
+    /// laid out in leaves.
+    ///
+    /// Removed entries that still occupy a slot are represented as `None`. This is a diagnostic
+    /// method that is only available in debug builds.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use scc::TreeIndex;
+    /// use scc::ebr::Guard;
+    ///
+    /// let treeindex: TreeIndex<u64, u32> = TreeIndex::new();
+    ///
+    /// assert!(treeindex.insert(2, 2).is_ok());
+    /// assert!(treeindex.insert(1, 1).is_ok());
+    /// assert!(treeindex.insert(3, 3).is_ok());
+    /// assert!(treeindex.remove(&1));
+    ///
+    /// let guard = Guard::new();
+    /// let physical = treeindex.debug_iter_physical(&guard).collect::<Vec<_>>();
+    /// assert_eq!(physical, vec![Some((&2, &2)), None, Some((&3, &3))]);
+    /// ```
+    #[cfg(debug_assertions)]
+    #[inline]
+    pub fn debug_iter_physical<'g>(
+        &self,
+        guard: &'g Guard,
+    ) -> impl Iterator<Item = Option<(&'g K, &'g V)>> {
+        let mut entries = Vec::new();
+        let mut leaf = self
+            .root
+            .load(Acquire, guard)
+            .as_ref()
+            .and_then(|root_ref| root_ref.min(guard))
+            .map(|scanner| scanner.leaf());
+        while let Some(leaf_ref) = leaf {
+            entries.extend(leaf_ref.physical_entries());
+            leaf = leaf_ref.next_ptr(Acquire, guard).as_ref();
+        }
+        entries.into_iter()
+    }
+
+    /// Returns up to `before` entries preceding the key, the entry associated with the key if it
+    /// exists, and up to `after` entries following the key, in ascending order.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use scc::TreeIndex;
+    /// use scc::ebr::Guard;
+    ///
+    /// let treeindex: TreeIndex<u64, u32> = TreeIndex::new();
+    ///
+    /// for k in 0..8 {
+    ///     assert!(treeindex.insert(k * 2, 0).is_ok());
+    /// }
+    ///
+    /// let guard = Guard::new();
+    /// let keys = |entries: Vec<(&u64, &u32)>| entries.into_iter().map(|(k, _)| *k).collect::<Vec<_>>();
+    /// assert_eq!(keys(treeindex.window(&6, 2, 1, &guard)), vec![2, 4, 6, 8]);
+    /// assert_eq!(keys(treeindex.window(&7, 2, 1, &guard)), vec![4, 6, 8]);
+    /// assert_eq!(keys(treeindex.window(&0, 2, 1, &guard)), vec![0, 2]);
+    /// ```
+    #[inline]
+    pub fn window<'g, Q>(
+        &self,
+        key: &Q,
+        before: usize,
+        after: usize,
+        guard: &'g Guard,
+    ) -> Vec<(&'g K, &'g V)>
+    where
+        K: Borrow<Q>,
+        Q: Ord + ?Sized,
+    {
+        let mut predecessors = VecDeque::new();
+        let mut entry = None;
+        let mut successors = Vec::new();
+        self.scan_from(key, guard, |k, v| match k.borrow().cmp(key) {
+            Ordering::Less => {
+                if before != 0 {
+                    if predecessors.len() == before {
+                        predecessors.pop_front();
+                    }
+                    predecessors.push_back((k, v));
+                }
+                true
+            }
+            Ordering::Equal => {
+                entry.replace((k, v));
+                after != 0
+            }
+            Ordering::Greater => {
+                if successors.len() < after {
+                    successors.push((k, v));
+                }
+                successors.len() < after
+            }
+        });
+
+        // Scanning started from an entry close to the key, therefore more entries may precede.
+        self.prepend_predecessors(&mut predecessors, before, guard);
+
+        predecessors
+            .into_iter()
+            .chain(entry)
+            .chain(successors)
+            .collect()
+    }
+
+    /// Removes entries in ascending key order until `should_stop` returns `true`.
+    ///
+    /// `should_stop` is invoked before each removal, e.g., to check whether enough memory has
+    /// been freed. Returns the number of removed entries; it stops early if no entries remain.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use scc::TreeIndex;
+    ///
+    /// let treeindex: TreeIndex<u64, u32> = TreeIndex::new();
+    ///
+    /// for k in 0..64 {
+    ///     assert!(treeindex.insert(k, 0).is_ok());
+    /// }
+    ///
+    /// let mut budget = 4;
+    /// let should_stop = || {
+    ///     budget -= 1;
+    ///     budget < 0
+    /// };
+    /// assert_eq!(treeindex.evict_until(should_stop), 4);
+    /// assert!(!treeindex.contains(&3));
+    /// assert!(treeindex.contains(&4));
+    /// assert_eq!(treeindex.evict_until(|| false), 60);
+    /// ```
+    #[inline]
+    pub fn evict_until<F: FnMut() -> bool>(&self, mut should_stop: F) -> usize {
+        let guard = Guard::new();
+        let mut num_evicted = 0;
+        for (k, _) in self.iter(&guard) {
+            if should_stop() {
+                break;
+            }
+            if self.remove(k) {
+                num_evicted += 1;
+            }
+        }
+        num_evicted
+    }
+
+    /// Returns clones of the `len` smallest entries in ascending order.
+    ///
+    /// The traversal stops as soon as `len` entries are collected.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use scc::TreeIndex;
+    ///
+    /// let treeindex: TreeIndex<u64, u32> = TreeIndex::new();
+    ///
+    /// for k in 0..64 {
+    ///     assert!(treeindex.insert(k, 0).is_ok());
+    /// }
+    ///
+    /// assert_eq!(treeindex.take_first(2), vec![(0, 0), (1, 0)]);
+    /// assert_eq!(treeindex.take_first(128).len(), 64);
+    /// ```
+    #[inline]
+    pub fn take_first(&self, len: usize) -> Vec<(K, V)> {
+        let guard = Guard::new();
+        self.iter(&guard)
+            .take(len)
+            .map(|(k, v)| (k.clone(), v.clone()))
+            .collect()
+    }
+
+    /// Returns clones of the `len` largest entries in ascending order.
+    ///
+    /// The traversal starts from the rightmost leaf, and proceeds backwards until `len` entries
+    /// are collected.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use scc::TreeIndex;
+    ///
+    /// let treeindex: TreeIndex<u64, u32> = TreeIndex::new();
+    ///
+    /// for k in 0..64 {
+    ///     assert!(treeindex.insert(k, 0).is_ok());
+    /// }
+    ///
+    /// assert_eq!(treeindex.take_last(2), vec![(62, 0), (63, 0)]);
+    /// assert_eq!(treeindex.take_last(128).len(), 64);
+    /// ```
+    #[inline]
+    pub fn take_last(&self, len: usize) -> Vec<(K, V)> {
+        if len == 0 {
+            return Vec::new();
+        }
+
+        let guard = Guard::new();
+        let mut entries = VecDeque::new();
+        if let Some(leaf) = self
+            .root
+            .load(Acquire, &guard)
+            .as_ref()
+            .and_then(|root_ref| root_ref.unbounded_leaf(&guard))
+        {
+            for entry in Scanner::new(leaf) {
+                if entries.len() == len {
+                    entries.pop_front();
+                }
+                entries.push_back(entry);
+            }
+        }
+        if entries.is_empty() {
+            // The rightmost leaf is empty: scan all the entries.
+            for entry in self.iter(&guard) {
+                if entries.len() == len {
+                    entries.pop_front();
+                }
+                entries.push_back(entry);
+            }
+        } else {
+            self.prepend_predecessors(&mut entries, len, &guard);
+        }
+        entries
+            .into_iter()
+            .map(|(k, v)| (k.clone(), v.clone()))
+            .collect()
+    }
+
+    /// Removes the entry with the minimum key, and returns it.
+    ///
+    /// Returns `None` if the [`TreeIndex`] is empty. Each entry is returned to only one of the
+    /// threads popping entries concurrently.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use scc::TreeIndex;
+    ///
+    /// let treeindex: TreeIndex<u64, u32> = TreeIndex::new();
+    /// assert!(treeindex.pop_min().is_none());
+    ///
+    /// assert!(treeindex.insert(2, 20).is_ok());
+    /// assert!(treeindex.insert(1, 10).is_ok());
+    /// assert_eq!(treeindex.pop_min(), Some((1, 10)));
+    /// assert_eq!(treeindex.pop_min(), Some((2, 20)));
+    /// assert!(treeindex.pop_min().is_none());
+    /// ```
+    #[inline]
+    pub fn pop_min(&self) -> Option<(K, V)> {
+        loop {
+            let guard = Guard::new();
+            let (key, _) = self.iter(&guard).next()?;
+            if let Some(entry) = self.pop(key) {
+                return Some(entry);
+            }
+        }
+    }
+
+    /// Removes the entry with the maximum key, and returns it.
+    ///
+    /// Returns `None` if the [`TreeIndex`] is empty. Each entry is returned to only one of the
+    /// threads popping entries concurrently.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use scc::TreeIndex;
+    ///
+    /// let treeindex: TreeIndex<u64, u32> = TreeIndex::new();
+    /// assert!(treeindex.pop_max().is_none());
+    ///
+    /// assert!(treeindex.insert(1, 10).is_ok());
+    /// assert!(treeindex.insert(2, 20).is_ok());
+    /// assert_eq!(treeindex.pop_max(), Some((2, 20)));
+    /// assert_eq!(treeindex.pop_max(), Some((1, 10)));
+    /// assert!(treeindex.pop_max().is_none());
+    /// ```
+    #[inline]
+    pub fn pop_max(&self) -> Option<(K, V)> {
+        loop {
+            let (key, _) = self.take_last(1).pop()?;
+            if let Some(entry) = self.pop(&key) {
+                return Some(entry);
+            }
+        }
+    }
+
+    /// Inserts a key-value pair, and then removes and returns the entry with the minimum key if
+    /// the [`TreeIndex`] holds more than `capacity` entries.
+    ///
+    /// Feeding ascending keys keeps the latest `capacity` entries in the [`TreeIndex`]. The
+    /// insertion and the removal are not a single atomic operation, however each call removes at
+    /// most one entry, and concurrent calls never remove the same entry; the [`TreeIndex`] may
+    /// momentarily hold more than `capacity` entries while other threads are rotating it.
+    ///
+    /// The number of entries is counted only by the first call and by the first call after the
+    /// root of the [`TreeIndex`] is replaced, e.g., by [`TreeIndex::clear`]; other calls maintain
+    /// the count in constant time. Entries inserted or removed by other methods are therefore not
+    /// taken into account until the root is replaced, and concurrent calls right after a
+    /// replacement may leave the count off by the number of those calls.
+    ///
+    /// Returns `None` without modifying the [`TreeIndex`] if the key exists.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use scc::TreeIndex;
+    ///
+    /// let treeindex: TreeIndex<u64, u32> = TreeIndex::new();
+    ///
+    /// assert_eq!(treeindex.rotate(1, 10, 2), None);
+    /// assert_eq!(treeindex.rotate(2, 20, 2), None);
+    /// assert_eq!(treeindex.rotate(3, 30, 2), Some((1, 10)));
+    /// assert_eq!(treeindex.rotate(3, 31, 2), None);
+    /// assert_eq!(treeindex.len(), 2);
+    /// ```
+    #[inline]
+    pub fn rotate(&self, key: K, val: V, capacity: usize) -> Option<(K, V)> {
+        if self.insert(key, val).is_err() {
+            return None;
+        }
+        let num_replacements = self.num_replacements.load(Acquire);
+        let len = if self.rotate_len_replacements.load(Acquire) == num_replacements {
+            self.rotate_len.fetch_add(1, AcqRel) + 1
+        } else {
+            let len = self.len();
+            self.rotate_len.store(len, Release);
+            self.rotate_len_replacements
+                .store(num_replacements, Release);
+            len
+        };
+        if len <= capacity {
+            return None;
+        }
+        loop {
+            let key = self.iter(&Guard::new()).next().map(|(k, _)| k.clone())?;
+            if let Some(entry) = self.pop(&key) {
+                self.rotate_len.fetch_sub(1, AcqRel);
+                return Some(entry);
+            }
+        }
+    }
+
+    /// Appends clones of at most `max` entries to the supplied buffer in ascending key order, and
+    /// returns the key of the next entry.
+    ///
+    /// The scan starts from `start` inclusive, or from the minimum key if `start` is `None`, and
+    /// passing the returned key as `start` resumes the scan. `None` is returned if no entries are
+    /// left. Entries in the buffer are not cleared, therefore the buffer can be reused without
+    /// allocating memory again.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use scc::TreeIndex;
+    ///
+    /// let treeindex: TreeIndex<u64, u32> = TreeIndex::new();
+    ///
+    /// for k in 0..5 {
+    ///     assert!(treeindex.insert(k, 0).is_ok());
+    /// }
+    ///
+    /// let mut buffer = Vec::with_capacity(3);
+    /// let next = treeindex.scan_into(&mut buffer, None, 3);
+    /// assert_eq!(buffer, vec![(0, 0), (1, 0), (2, 0)]);
+    /// assert_eq!(next, Some(3));
+    ///
+    /// buffer.clear();
+    /// assert!(treeindex.scan_into(&mut buffer, next.as_ref(), 3).is_none());
+    /// assert_eq!(buffer, vec![(3, 0), (4, 0)]);
+    /// ```
+    #[inline]
+    pub fn scan_into(&self, out: &mut Vec<(K, V)>, start: Option<&K>, max: usize) -> Option<K> {
+        let guard = Guard::new();
+        let start_bound = start.map_or(Unbounded, |k| Included(k.clone()));
+        for (num_appended, (k, v)) in self.range((start_bound, Unbounded), &guard).enumerate() {
+            if num_appended == max {
+                return Some(k.clone());
+            }
+            out.push((k.clone(), v.clone()));
+        }
+        None
+    }
+
+    /// Returns a [`Range`] that resumes a scan from the given [`ScanToken`].
+    ///
+    /// The [`Range`] starts from the first key that was not yet visited by the [`Range`] that
+    /// issued the token, and has the same end bound as that [`Range`].
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use scc::TreeIndex;
+    /// use scc::ebr::Guard;
+    ///
+    /// let treeindex: TreeIndex<u64, u32> = TreeIndex::new();
+    ///
+    /// for k in 0..8 {
+    ///     assert!(treeindex.insert(k, 0).is_ok());
+    /// }
+    ///
+    /// let guard = Guard::new();
+    /// let mut range = treeindex.range(2.., &guard);
+    /// assert_eq!(range.next(), Some((&2, &0)));
+    /// let token = range.token().unwrap();
+    /// drop(guard);
+    ///
+    /// let guard = Guard::new();
+    /// assert_eq!(treeindex.resume(token, &guard).count(), 5);
+    ///
+    /// let mut range = treeindex.range(2..4, &guard);
+    /// assert_eq!(range.next(), Some((&2, &0)));
+    /// let token = range.token().unwrap();
+    /// assert_eq!(treeindex.resume(token, &guard).count(), 1);
+    /// ```
+    #[inline]
+    pub fn resume<'t, 'g>(
+        &'t self,
+        token: ScanToken<K>,
+        guard: &'g Guard,
+    ) -> Range<'t, 'g, K, V, (Bound<K>, Bound<K>)> {
+        Range::new(&self.root, (token.start, token.end), guard)
+    }
+
+    /// Touches the leaves covering the given range so that subsequent reads of the range do not
+    /// incur cache misses or page faults.
+    ///
+    /// Returns the number of leaves that overlap the range.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use scc::TreeIndex;
+    ///
+    /// let treeindex: TreeIndex<u64, u32> = TreeIndex::new();
+    /// assert_eq!(treeindex.prefetch_range(..), 0);
+    ///
+    /// for k in 0..64 {
+    ///     assert!(treeindex.insert(k, 0).is_ok());
+    /// }
+    ///
+    /// assert_eq!(treeindex.prefetch_range(4..=4), 1);
+    /// assert!(treeindex.prefetch_range(..) > 1);
+    /// ```
+    #[inline]
+    pub fn prefetch_range<R: RangeBounds<K>>(&self, range: R) -> usize {
+        let guard = Guard::new();
+        let Some(root_ref) = self.root.load(Acquire, &guard).as_ref() else {
+            return 0;
+        };
+        let first_leaf = match range.start_bound() {
+            Excluded(key) | Included(key) => root_ref.max_le_appr(key, &guard),
+            Unbounded => None,
+        }
+        .or_else(|| root_ref.min(&guard))
+        .map(|scanner| scanner.leaf());
+
+        let mut num_leaves = 0;
+        let leaves =
+            std::iter::successors(first_leaf, |leaf| leaf.next_ptr(Acquire, &guard).as_ref());
+        for leaf in leaves {
+            let mut scanner = Scanner::new(leaf);
+            let Some((min_key, _)) = scanner.next() else {
+                continue;
+            };
+            let max_key = scanner.last().map_or(min_key, |(k, _)| k);
+            let below_start = match range.start_bound() {
+                Excluded(key) => max_key <= key,
+                Included(key) => max_key < key,
+                Unbounded => false,
+            };
+            let above_end = match range.end_bound() {
+                Excluded(key) => min_key >= key,
+                Included(key) => min_key > key,
+                Unbounded => false,
+            };
+            if above_end {
+                break;
+            } else if !below_start {
+                num_leaves += 1;
+            }
+        }
+        num_leaves
+    }
+
+    /// Computes a checksum of the key-value pairs in the given range.
+    ///
+    /// The hash values of key-value pairs are folded in ascending key order, therefore two
+    /// [`TreeIndex`] instances yield the same checksum for a range if they contain the same
+    /// key-value pairs in the range.
+    ///
+    /// The hash function is 64-bit FNV-1a, and integers are fed to it in little-endian byte order
+    /// with `usize` widened to 64 bits, therefore checksums do not depend on the Rust release, the
+    /// build, or the platform as long as the [`Hash`] implementations of `K` and `V` stay the same.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use scc::TreeIndex;
+    ///
+    /// let treeindex: TreeIndex<u64, u32> = TreeIndex::new();
+    /// let replica: TreeIndex<u64, u32> = TreeIndex::new();
+    ///
+    /// for k in 0..16 {
+    ///     assert!(treeindex.insert(k, 0).is_ok());
+    ///     assert!(replica.insert(15 - k, 0).is_ok());
+    /// }
+    /// assert_eq!(treeindex.range_checksum(4..8), replica.range_checksum(4..8));
+    ///
+    /// assert!(replica.remove(&6));
+    /// assert!(replica.insert(6, 1).is_ok());
+    /// assert_ne!(treeindex.range_checksum(4..8), replica.range_checksum(4..8));
+    /// ```
+    #[inline]
+    pub fn range_checksum<R: RangeBounds<K>>(&self, range: R) -> u64
+    where
+        K: Hash,
+        V: Hash,
+    {
+        let guard = Guard::new();
+        let mut hasher = Fnv1aHasher::default();
+        for (k, v) in self.range(range, &guard) {
+            k.hash(&mut hasher);
+            v.hash(&mut hasher);
+        }
+        hasher.finish()
+    }
+
+    /// Iterates over entries leaf by leaf, handing over clones of the entries in each leaf to the
+    /// supplied closure.
+    ///
+    /// A [`Guard`] is only held while the entries of a leaf are being cloned, and the next leaf
+    /// is looked up again by the last key of the previous one; this allows memory to be reclaimed
+    /// during a long scan at the cost of cloning every entry.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use scc::TreeIndex;
+    ///
+    /// let treeindex: TreeIndex<u64, u32> = TreeIndex::new();
+    ///
+    /// for k in 0..64 {
+    ///     assert!(treeindex.insert(k, 0).is_ok());
+    /// }
+    ///
+    /// let mut keys = Vec::new();
+    /// treeindex.for_each_leafwise(|entries| keys.extend(entries.iter().map(|(k, _)| *k)));
+    /// assert_eq!(keys, (0..64).collect::<Vec<_>>());
+    /// ```
+    #[inline]
+    pub fn for_each_leafwise<F: FnMut(&[(K, V)])>(&self, mut f: F) {
+        let mut entries: Vec<(K, V)> = Vec::new();
+        let mut last_key: Option<K> = None;
+        loop {
+            entries.clear();
+            if !Self::clone_leaf_after(&self.root, last_key.as_ref(), &mut entries) {
+                return;
+            }
+            last_key = entries.last().map(|(k, _)| k.clone());
+            f(&entries);
+        }
+    }
+
+    /// Iterates over entries in ascending key order until the deadline passes.
+    ///
+    /// The deadline is checked each time the scan moves on to the next leaf, and at least one
+    /// leaf is always scanned. If the deadline passes before all the entries are visited,
+    /// [`ScanStatus::TimedOut`] is returned along with the last key visited, and the scan can be
+    /// resumed by [`TreeIndex::scan_after`].
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use scc::TreeIndex;
+    /// use scc::ebr::Guard;
+    /// use scc::tree_index::ScanStatus;
+    /// use std::time::{Duration, Instant};
+    ///
+    /// let treeindex: TreeIndex<u64, u32> = TreeIndex::new();
+    ///
+    /// for k in 0..1024 {
+    ///     assert!(treeindex.insert(k, 0).is_ok());
+    /// }
+    ///
+    /// let deadline = Instant::now() + Duration::from_secs(60);
+    /// let mut num_visited = 0;
+    /// let status = treeindex.for_each_until_deadline(|_, _| num_visited += 1, deadline);
+    /// assert_eq!(status, ScanStatus::Completed);
+    /// assert_eq!(num_visited, 1024);
+    ///
+    /// let mut keys = Vec::new();
+    /// let status = treeindex.for_each_until_deadline(|k, _| keys.push(*k), Instant::now());
+    /// let ScanStatus::TimedOut(last_key) = status else {
+    ///     unreachable!();
+    /// };
+    /// assert_eq!(keys.last(), Some(&last_key));
+    ///
+    /// let guard = Guard::new();
+    /// keys.extend(treeindex.scan_after(&last_key, &guard).map(|(k, _)| *k));
+    /// assert_eq!(keys, (0..1024).collect::<Vec<_>>());
+    /// ```
+    #[inline]
+    pub fn for_each_until_deadline<F: FnMut(&K, &V)>(
+        &self,
+        mut f: F,
+        deadline: Instant,
+    ) -> ScanStatus<K> {
+        let guard = Guard::new();
+        let mut last_key: Option<&K> = None;
+        loop {
+            if let Some(k) = last_key {
+                if Instant::now() >= deadline {
+                    return ScanStatus::TimedOut(k.clone());
+                }
+            }
+            let start_bound = last_key.map_or(Unbounded, Excluded);
+            let mut range = Range::new(&self.root, (start_bound, Unbounded), &guard);
+            let Some((k, v)) = range.next() else {
+                return ScanStatus::Completed;
+            };
+            f(k, v);
+            last_key.replace(k);
+            if let Some(leaf_scanner) = range.leaf_scanner.take() {
+                for (k, v) in leaf_scanner {
+                    f(k, v);
+                    last_key.replace(k);
+                }
+            }
+        }
+    }
+
+    /// Prepends entries preceding the first entry in `entries` until `entries` contains `len`
+    /// entries or there are no more preceding entries.
+    fn prepend_predecessors<'g>(
+        &self,
+        entries: &mut VecDeque<(&'g K, &'g V)>,
+        len: usize,
+        guard: &'g Guard,
+    ) {
+        while entries.len() < len {
+            let Some(&(upper_key, _)) = entries.front() else {
+                break;
+            };
+            let mut segment = Vec::new();
+            self.scan_from::<K, _>(upper_key, guard, |k, v| {
+                if k < upper_key {
+                    segment.push((k, v));
+                    return true;
+                }
+                false
+            });
+            if segment.is_empty() {
+                break;
+            }
+            let num_required = (len - entries.len()).min(segment.len());
+            for e in segment.into_iter().rev().take(num_required) {
+                entries.push_front(e);
+            }
+        }
+    }
+
+    /// Scans entries in ascending order from an entry that is close enough to the entry with the
+    /// maximum key among those keys smaller than or equal to the given key, or from the minimum
+    /// entry if there is none, until `f` returns `false`.
+    fn scan_from<'g, Q, F: FnMut(&'g K, &'g V) -> bool>(&self, key: &Q, guard: &'g Guard, mut f: F)
+    where
+        K: Borrow<Q>,
+        Q: Ord + ?Sized,
+    {
+        let Some(root_ref) = self.root.load(Acquire, guard).as_ref() else {
+            return;
+        };
+        let mut scanner = if let Some(scanner) = root_ref.max_le_appr(key, guard) {
+            scanner
+        } else {
+            let Some(mut scanner) = root_ref.min(guard) else {
+                return;
+            };
+            if scanner.next().is_none() {
+                let Some(next_scanner) = scanner.jump::<K>(None, guard) else {
+                    return;
+                };
+                scanner = next_scanner;
+            }
+            scanner
+        };
+        while let Some((k, v)) = scanner.get() {
+            if !f(k, v) {
+                return;
+            }
+            if scanner.next().is_none() {
+                let Some(next_scanner) = scanner.jump::<K>(Some(k), guard) else {
+                    return;
+                };
+                scanner = next_scanner;
+            }
+        }
+    }
+
+    /// Replaces the value associated with the key if `updater` returns a new value.
+    ///
+    /// Returns `true` if the value was replaced. `updater` may be invoked more than once if the
+    /// entry is concurrently modified.
+    fn update<Q, F: FnMut(&V) -> Option<V>>(&self, key: &Q, mut updater: F) -> bool
+    where
+        K: Borrow<Q>,
+        Q: Ord + ?Sized,
+    {
+        loop {
+            let guard = Guard::new();
+            let root_ptr = self.root.load(Acquire, &guard);
+            let Some(root_ref) = root_ptr.as_ref() else {
+                return false;
+            };
+            match root_ref.update(key, &mut updater, &mut (), &guard) {
+                Ok(UpdateResult::Success) => return true,
+                Ok(UpdateResult::Fail) => return false,
+                Ok(UpdateResult::Full(k, v)) => {
+                    let _result = Node::split_root(root_ptr, &self.root, k, v, &guard);
+                }
+                Ok(UpdateResult::Frozen) | Err(()) => {
+                    root_ref.cleanup_link(key, false, &guard);
+                }
+                Ok(UpdateResult::Retired) => {
+                    let _result = Node::cleanup_root(&self.root, &mut (), &guard);
+                }
+            }
+        }
+    }
+
+    /// Returns a clone of the first entry after `last_key`, or the first entry if `last_key` is
+    /// `None`.
+    fn next_entry_after(&self, last_key: Option<&K>) -> Option<(K, V)> {
+        let guard = Guard::new();
+        let entry = if let Some(k) = last_key {
+            self.scan_after(k, &guard).next()
+        } else {
+            self.iter(&guard).next()
+        };
+        entry.map(|(k, v)| (k.clone(), v.clone()))
+    }
+
+    /// Inserts a key-value pair using the supplied [`Guard`] if the depth of the [`TreeIndex`] can
+    /// be kept equal to or less than `max_depth`.
+    #[inline]
+    fn insert_with_guard(
+        &self,
+        mut key: K,
+        mut val: V,
+        max_depth: usize,
+        guard: &Guard,
+    ) -> Result<(), InsertError<K, V>> {
+        let mut new_root = None;
+        loop {
+            let root_ptr = self.root.load(Acquire, guard);
+            if let Some(root_ref) = root_ptr.as_ref() {
+                match root_ref.insert(key, val, &mut (), guard) {
+                    Ok(r) => match r {
+                        InsertResult::Success => return Ok(()),
+                        InsertResult::Frozen(k, v) | InsertResult::Retry(k, v) => {
+                            key = k;
+                            val = v;
+                            root_ref.cleanup_link(&key, false, guard);
+                        }
+                        InsertResult::Duplicate(k, v) => {
+                            return Err(InsertError::Duplicate(k, v));
+                        }
+                        InsertResult::Full(k, v) => {
+                            if max_depth != usize::MAX && root_ref.depth(1, guard) >= max_depth {
+                                // Splitting the locked root would make the tree deeper.
+                                root_ref.rollback(guard);
+                                return Err(InsertError::Overflow(k, v));
+                            }
+                            let (k, v) = Node::split_root(root_ptr, &self.root, k, v, guard);
+                            key = k;
+                            val = v;
+                            continue;
+                        }
+                        InsertResult::Retired(k, v) => {
+                            key = k;
+                            val = v;
+                            let _result = Node::cleanup_root(&self.root, &mut (), guard);
+                        }
+                    },
+                    Err((k, v)) => {
+                        key = k;
+                        val = v;
+                    }
+                }
+            }
+
+            if max_depth == 0 && root_ptr.is_null() {
+                return Err(InsertError::Overflow(key, val));
+            }
+            let node = if let Some(new_root) = new_root.take() {
+                new_root
+            } else {
+                Shared::new(Node::new_leaf_node())
+            };
+            if let Err((node, _)) = self.root.compare_exchange(
+                Ptr::null(),
+                (Some(node), Tag::None),
+                AcqRel,
+                Acquire,
+                guard,
+            ) {
+                new_root = node;
+            }
+        }
+    }
+
+    /// Removes a key-value pair if the given condition is met using the supplied [`Guard`].
+    #[inline]
+    fn remove_if_with_guard<Q, F: FnMut(&V) -> bool>(
+        &self,
+        key: &Q,
+        condition: &mut F,
+        guard: &Guard,
+    ) -> bool
+    where
+        K: Borrow<Q>,
+        Q: Ord + ?Sized,
+    {
+        let mut removed = false;
+        loop {
+            if let Some(root_ref) = self.root.load(Acquire, guard).as_ref() {
+                if let Ok(result) = root_ref.remove_if::<_, _, _>(key, condition, &mut (), guard) {
+                    if matches!(result, RemoveResult::Cleanup) {
+                        root_ref.cleanup_link(key, false, guard);
+                    }
+                    match result {
+                        RemoveResult::Success => return true,
+                        RemoveResult::Cleanup | RemoveResult::Retired => {
+                            if Node::cleanup_root(&self.root, &mut (), guard) {
+                                return true;
+                            }
+                            removed = true;
+                        }
+                        RemoveResult::Fail => {
+                            if removed {
+                                if Node::cleanup_root(&self.root, &mut (), guard) {
+                                    return true;
+                                }
+                            } else {
+                                return false;
+                            }
+                        }
+                        RemoveResult::Frozen => (),
+                    }
+                }
+            } else {
+                return removed;
+            }
+        }
+    }
+
+    /// Appends clones of the first entry after `last_key` and of the following entries in the
+    /// same leaf to `entries`.
+    ///
+    /// The scan starts from the minimum key if `last_key` is `None`. Returns `false` if there is
+    /// no entry after `last_key`.
+    fn clone_leaf_after<E: Extend<(K, V)>>(
+        root: &AtomicShared<Node<K, V>>,
+        last_key: Option<&K>,
+        entries: &mut E,
+    ) -> bool {
+        let guard = Guard::new();
+        let start_bound = last_key.map_or(Unbounded, Excluded);
+        let mut range = Range::new(root, (start_bound, Unbounded), &guard);
+        let Some((k, v)) = range.next() else {
+            return false;
+        };
+        entries.extend(Some((k.clone(), v.clone())));
+        if let Some(leaf_scanner) = range.leaf_scanner.take() {
+            entries.extend(leaf_scanner.map(|(k, v)| (k.clone(), v.clone())));
+        }
+        true
+    }
+
+    /// Locks all the nodes and freezes all the leaves of the tree, and returns the root.
+    ///
+    /// Until the lockers pushed into `lockers` are dropped, threads trying to modify the tree
+    /// wait, and then they retry from the root.
+    fn freeze<'g>(
+        &self,
+        lockers: &mut Vec<Locker<'g, K, V>>,
+        guard: &'g Guard,
+    ) -> Ptr<'g, Node<K, V>> {
+        loop {
+            let root_ptr = self.root.load(Acquire, guard);
+            if root_ptr
+                .as_ref()
+                .map_or(true, |root_ref| root_ref.freeze(lockers, guard))
+            {
+                return root_ptr;
+            }
+
+            // A node is locked by another thread that is modifying the tree.
+            Self::thaw(lockers.drain(..), guard);
+            yield_now();
+        }
+    }
+
+    /// Thaws the leaves frozen by [`TreeIndex::freeze`], and unlocks the nodes.
+    fn thaw<'g, I: IntoIterator<Item = Locker<'g, K, V>>>(lockers: I, guard: &Guard)
+    where
+        K: 'g,
+        V: 'g,
+    {
+        for locker in lockers {
+            locker.thaw(guard);
+        }
+    }
+
+    /// Replaces the root with that of `rebuilt` if the root is `expected`, or unconditionally if
+    /// `expected` is `None`.
+    ///
+    /// Returns `false` if the root is not `expected`.
+    fn install_root(
+        &self,
+        rebuilt: &TreeIndex<K, V>,
+        expected: Option<Ptr<Node<K, V>>>,
+        guard: &Guard,
+    ) -> bool {
+        let new_root = rebuilt.root.swap((None, Tag::None), Acquire).0;
+        let old_root = if let Some(expected) = expected {
+            match self.root.compare_exchange(
+                expected,
+                (new_root, Tag::None),
+                AcqRel,
+                Acquire,
+                guard,
+            ) {
+                Ok((old_root, _)) => old_root,
+                Err(_) => return false,
+            }
+        } else {
+            self.root.swap((new_root, Tag::None), AcqRel).0
+        };
+        self.num_replacements.fetch_add(1, Release);
+        if let Some(old_root) = old_root {
+            // Leaves are unlinked only after readers of the old tree are gone, so that they can
+            // still move on to the next leaf.
+            guard.defer_execute(move || old_root.clear(&Guard::new()));
+        }
+        true
+    }
+
+    /// Removes the entry, and returns it if removed by this call.
+    fn pop(&self, key: &K) -> Option<(K, V)> {
+        let mut removed = None;
+        if self.remove_if(key, |v| {
+            removed.replace(v.clone());
+            true
+        }) {
+            removed.map(|v| (key.clone(), v))
+        } else {
+            None
+        }
+    }
+
+    /// Replaces, removes, or inserts the entry according to the value `f` computes from the
+    /// current one.
+    fn compute<F: FnMut(&K, Option<&V>) -> Option<V>>(&self, key: &K, f: &mut F, guard: &Guard) {
+        loop {
+            let mut found = false;
+            let mut removal = None;
+            if self.update(key, |v| {
+                found = true;
+                let new_val = f(key, Some(v));
+                if new_val.is_none() {
+                    removal.replace(v as *const V);
+                }
+                new_val
+            }) {
+                return;
+            }
+            if found {
+                if let Some(removal) = removal {
+                    if self.remove_computed(key, removal, guard) {
+                        return;
+                    }
+                }
+            } else {
+                match f(key, None) {
+                    Some(val) => {
+                        if self.insert(key.clone(), val).is_ok() {
+                            return;
+                        }
+                    }
+                    None => return,
+                }
+            }
+        }
+    }
+
+    /// Removes the entry if its value is still the one that `f` computed the removal from.
+    ///
+    /// Values are never overwritten in place, and `guard` keeps the leaf from being reclaimed, so
+    /// the address tells whether the entry has been modified since.
+    fn remove_computed(&self, key: &K, removal: *const V, guard: &Guard) -> bool {
+        self.remove_if_with_guard(key, &mut |v: &V| ptr::eq(v, removal), guard)
+    }
+
+    /// Reverses the order of the separators in every node, leaving the leaves intact.
+    #[cfg(test)]
+    pub(crate) fn scramble_separators(&self) {
+        let guard = Guard::new();
+        if let Some(root_ref) = self.root.load(Acquire, &guard).as_ref() {
+            root_ref.scramble_separators(&guard);
+        }
+    }
+
+    /// Checks if the separators in every node bound the keys below them.
+    #[cfg(test)]
+    pub(crate) fn validate(&self) -> bool {
+        let guard = Guard::new();
+        self.root
+            .load(Acquire, &guard)
+            .as_ref()
+            .map_or(true, |root_ref| root_ref.validate(None, None, &guard))
+    }
+}
+
+impl<K, V> TreeIndex<K, Option<V>>
+where
+    K: 'static + Clone + Ord,
+    V: 'static + Clone,
+{
+    /// Inserts a key with a placeholder value that is to be filled in later by
+    /// [`TreeIndex::fulfill`].
+    ///
+    /// A reserved key is associated with `None` until it is fulfilled, so that readers can
+    /// distinguish reserved keys from those that have a value.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error along with the supplied key if the key exists.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use scc::TreeIndex;
+    ///
+    /// let treeindex: TreeIndex<u64, Option<u32>> = TreeIndex::new();
+    ///
+    /// assert!(treeindex.reserve_key(1).is_ok());
+    /// assert_eq!(treeindex.reserve_key(1), Err(1));
+    /// assert_eq!(treeindex.peek_with(&1, |_, v| *v), Some(None));
+    /// ```
+    #[inline]
+    pub fn reserve_key(&self, key: K) -> Result<(), K> {
+        self.insert(key, None).map_err(|(k, _)| k)
+    }
+
+    /// Fills in the value of a key reserved by [`TreeIndex::reserve_key`].
+    ///
+    /// # Errors
+    ///
+    /// Returns an error along with the supplied value if the key does not exist or the key has
+    /// already been associated with a value.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use scc::TreeIndex;
+    ///
+    /// let treeindex: TreeIndex<u64, Option<u32>> = TreeIndex::new();
+    ///
+    /// assert_eq!(treeindex.fulfill(&1, 10), Err(10));
+    /// assert!(treeindex.reserve_key(1).is_ok());
+    /// assert!(treeindex.fulfill(&1, 10).is_ok());
+    /// assert_eq!(treeindex.fulfill(&1, 11), Err(11));
+    /// assert_eq!(treeindex.peek_with(&1, |_, v| *v), Some(Some(10)));
+    /// ```
+    #[inline]
+    pub fn fulfill<Q>(&self, key: &Q, val: V) -> Result<(), V>
+    where
+        K: Borrow<Q>,
+        Q: Ord + ?Sized,
+    {
+        if self.update(key, |v| v.is_none().then(|| Some(val.clone()))) {
+            Ok(())
+        } else {
+            Err(val)
+        }
+    }
+}
+
+impl<K, V> TreeIndex<K, (u64, V)>
+where
+    K: 'static + Clone + Ord,
+    V: 'static + Clone,
+{
+    /// Replaces the value associated with the key if the current version of the entry is equal
+    /// to the expected one, and increments the version.
+    ///
+    /// Each entry is a pair of a version and a value. Unlike comparing values, comparing versions
+    /// detects that the entry was modified and then changed back to an equal value in the
+    /// meantime.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error along with the current version if the versions do not match, or `None` if
+    /// the key does not exist.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use scc::TreeIndex;
+    ///
+    /// let treeindex: TreeIndex<u64, (u64, u32)> = TreeIndex::new();
+    ///
+    /// assert_eq!(treeindex.compare_and_swap_versioned(&1, 0, 11), Err(None));
+    /// assert!(treeindex.insert(1, (0, 10)).is_ok());
+    /// assert_eq!(treeindex.compare_and_swap_versioned(&1, 0, 11), Ok(1));
+    /// assert_eq!(treeindex.compare_and_swap_versioned(&1, 0, 12), Err(Some(1)));
+    /// assert_eq!(treeindex.peek_with(&1, |_, v| *v), Some((1, 11)));
+    /// ```
+    #[inline]
+    pub fn compare_and_swap_versioned<Q>(
+        &self,
+        key: &Q,
+        expected_version: u64,
+        new: V,
+    ) -> Result<u64, Option<u64>>
+    where
+        K: Borrow<Q>,
+        Q: Ord + ?Sized,
+    {
+        let new_version = expected_version.wrapping_add(1);
+        if self.update(key, |(version, _)| {
+            (*version == expected_version).then(|| (new_version, new.clone()))
+        }) {
+            Ok(new_version)
+        } else {
+            Err(self.peek_with(key, |_, (version, _)| *version))
+        }
+    }
+}
+
+impl<K, V> TreeIndex<K, (Instant, V)>
+where
+    K: 'static + Clone + Ord,
+    V: 'static + Clone,
+{
+    /// Returns an [`Iterator`] over the entries stamped before the given [`Instant`] in ascending
+    /// key order.
+    ///
+    /// Each entry is a pair of an [`Instant`], e.g., when the entry was inserted, and a value.
+    /// Since the stamps are not ordered by key, it scans all the entries, and therefore the time
+    /// complexity is O(N).
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use scc::TreeIndex;
+    /// use scc::ebr::Guard;
+    /// use std::time::{Duration, Instant};
+    ///
+    /// let treeindex: TreeIndex<u64, (Instant, u32)> = TreeIndex::new();
+    ///
+    /// let now = Instant::now();
+    /// assert!(treeindex.insert(1, (now, 10)).is_ok());
+    /// assert!(treeindex.insert(2, (now + Duration::from_secs(1), 20)).is_ok());
+    ///
+    /// let guard = Guard::new();
+    /// let older_than = now + Duration::from_millis(1);
+    /// let mut iter = treeindex.stale_entries(older_than, &guard);
+    /// assert_eq!(iter.next(), Some((&1, &10)));
+    /// assert!(iter.next().is_none());
+    /// ```
+    #[inline]
+    #[allow(clippy::type_complexity)]
+    pub fn stale_entries<'t, 'g>(
+        &'t self,
+        older_than: Instant,
+        guard: &'g Guard,
+    ) -> std::iter::FilterMap<
+        Iter<'t, 'g, K, (Instant, V)>,
+        impl FnMut((&'g K, &'g (Instant, V))) -> Option<(&'g K, &'g V)>,
+    > {
+        self.iter(guard)
+            .filter_map(move |(k, (stamp, v))| (*stamp < older_than).then_some((k, v)))
+    }
+}
+
+impl<A, B, V> TreeIndex<(A, B), V>
+where
+    A: 'static + Clone + Ord,
+    B: 'static + Clone + Ord,
+    V: 'static + Clone,
+{
+    /// Returns an [`Iterator`] over the entries whose keys have the given first component in
+    /// ascending key order.
+    ///
+    /// The scan starts at the leaf found by descending the tree with the first component, and
+    /// stops at the first key with a greater first component.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use scc::TreeIndex;
+    /// use scc::ebr::Guard;
+    ///
+    /// let treeindex: TreeIndex<(u32, u32), u32> = TreeIndex::new();
+    ///
+    /// for a in 0..8 {
+    ///     for b in 0..8 {
+    ///         assert!(treeindex.insert((a, b), a * b).is_ok());
+    ///     }
+    /// }
+    ///
+    /// let guard = Guard::new();
+    /// let mut iter = treeindex.range_by_first(&3, &guard);
+    /// assert_eq!(iter.next(), Some((&(3, 0), &0)));
+    /// assert_eq!(iter.count(), 7);
+    /// ```
+    #[inline]
+    #[allow(clippy::type_complexity)]
+    pub fn range_by_first<'t, 'g>(
+        &'t self,
+        a: &A,
+        guard: &'g Guard,
+    ) -> std::iter::TakeWhile<
+        std::iter::SkipWhile<Iter<'t, 'g, (A, B), V>, impl FnMut(&(&'g (A, B), &'g V)) -> bool>,
+        impl FnMut(&(&'g (A, B), &'g V)) -> bool,
+    > {
+        let mut iter = Iter::new(self, guard);
+        if let Some(scanner) = self
+            .root
+            .load(Acquire, guard)
+            .as_ref()
+            .and_then(|root_ref| root_ref.max_le_appr(FirstOf::from_ref(a), guard))
+        {
+            // The leaf contains the maximum key with a smaller first component, or it is the
+            // minimum leaf; the scan restarts from the beginning of the leaf in the latter case.
+            iter.leaf_scanner.replace(Scanner::new(scanner.leaf()));
+        }
+        let (lower, upper) = (a.clone(), a.clone());
+        iter.skip_while(move |((k, _), _)| *k < lower)
+            .take_while(move |((k, _), _)| *k == upper)
+    }
+}
+
+impl<K, V> Clone for TreeIndex<K, V>
+where
+    K: 'static + Clone + Ord,
+    V: 'static + Clone,
+{
+    #[inline]
+    fn clone(&self) -> Self {
+        let self_clone = Self::default();
+        for (k, v) in self.iter(&Guard::new()) {
+            let _reuslt = self_clone.insert(k.clone(), v.clone());
+        }
+        self_clone
+    }
+}
+
+impl<K, V> Debug for TreeIndex<K, V>
+where
+    K: 'static + Clone + Debug + Ord,
+    V: 'static + Clone + Debug,
+{
+    #[inline]
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let guard = Guard::new();
+        f.debug_map().entries(self.iter(&guard)).finish()
+    }
+}
+
+impl<K, V> Default for TreeIndex<K, V> {
+    /// Creates a [`TreeIndex`] with the default parameters.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use scc::TreeIndex;
+    ///
+    /// let treeindex: TreeIndex<u64, u32> = TreeIndex::default();
+    /// ```
+    #[inline]
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<K, V> Drop for TreeIndex<K, V> {
+    #[inline]
+    fn drop(&mut self) {
+        self.clear();
+    }
+}
+
+impl<K, V> Extend<(K, V)> for TreeIndex<K, V>
+where
+    K: 'static + Clone + Ord,
+    V: 'static + Clone,
+{
+    /// Inserts the supplied entries.
+    ///
+    /// Entries whose keys exist are ignored as [`TreeIndex::insert`] rejects them. The entries are
+    /// inserted through a [`Pinned`] handle, so that a [`Guard`] is reused across insertions; the
+    /// [`Guard`] is renewed every 64 insertions for retired memory to be reclaimed.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use scc::TreeIndex;
+    ///
+    /// let mut treeindex: TreeIndex<u64, u32> = TreeIndex::new();
+    /// assert!(treeindex.insert(1, 10).is_ok());
+    ///
+    /// treeindex.extend([(1, 11), (2, 20)]);
+    /// assert_eq!(treeindex.peek_with(&1, |_, v| *v), Some(10));
+    /// assert_eq!(treeindex.peek_with(&2, |_, v| *v), Some(20));
+    /// ```
+    #[inline]
+    fn extend<I: IntoIterator<Item = (K, V)>>(&mut self, iter: I) {
+        let mut pinned = self.pinned(64);
+        for (k, v) in iter {
+            let _result = pinned.insert(k, v);
+        }
+    }
+}
+
+impl<K, V> FromIterator<(K, V)> for TreeIndex<K, V>
+where
+    K: 'static + Clone + Ord,
+    V: 'static + Clone,
+{
+    /// Creates a [`TreeIndex`] from the supplied entries.
+    ///
+    /// The entries are sorted, and then appended to the rightmost leaf one after another, so that
+    /// leaves are filled without being looked up from the root. If the same key appears more than
+    /// once, the last value is kept.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use scc::TreeIndex;
+    ///
+    /// let treeindex: TreeIndex<u64, u32> = [(2, 20), (1, 10), (2, 21)].into_iter().collect();
+    ///
+    /// assert_eq!(treeindex.len(), 2);
+    /// assert_eq!(treeindex.peek_with(&1, |_, v| *v), Some(10));
+    /// assert_eq!(treeindex.peek_with(&2, |_, v| *v), Some(21));
+    /// ```
+    #[inline]
+    fn from_iter<I: IntoIterator<Item = (K, V)>>(iter: I) -> Self {
+        let mut entries = iter.into_iter().collect::<Vec<_>>();
+        entries.sort_by(|(a, _), (b, _)| a.cmp(b));
+        let treeindex = Self::new();
+        let mut entries = entries.into_iter().peekable();
+        while let Some((k, v)) = entries.next() {
+            if entries.peek().map_or(true, |(next, _)| *next != k) {
+                let _result = treeindex.insert_at_tail(k, v);
+            }
+        }
+        treeindex
+    }
+}
+
+impl<K, V> IntoIterator for TreeIndex<K, V>
+where
+    K: 'static + Clone + Ord,
+    V: 'static + Clone,
+{
+    type Item = (K, V);
+    type IntoIter = TakeAll<K, V>;
+
+    /// Consumes the [`TreeIndex`], and returns an [`Iterator`] over its entries in ascending key
+    /// order.
+    ///
+    /// The entries are taken out of the leaves by [`TreeIndex::take_all`], and the nodes are
+    /// reclaimed once the returned [`Iterator`] is exhausted or dropped; the returned [`Iterator`]
+    /// does not hold a [`Guard`] between calls.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use scc::TreeIndex;
+    ///
+    /// let treeindex: TreeIndex<u64, u32> = TreeIndex::new();
+    /// assert!(treeindex.insert(2, 20).is_ok());
+    /// assert!(treeindex.insert(1, 10).is_ok());
+    ///
+    /// assert_eq!(treeindex.into_iter().collect::<Vec<_>>(), vec![(1, 10), (2, 20)]);
+    /// ```
+    #[inline]
+    fn into_iter(self) -> Self::IntoIter {
+        self.take_all()
+    }
+}
+
+impl<K, V> PartialEq for TreeIndex<K, V>
+where
+    K: 'static + Clone + Ord,
+    V: 'static + Clone + PartialEq,
+{
+    #[inline]
+    fn eq(&self, other: &Self) -> bool {
+        // The key order is preserved, therefore comparing iterators suffices.
+        let guard = Guard::new();
+        Iterator::eq(self.iter(&guard), other.iter(&guard))
+    }
+}
+
+impl<K, V> UnwindSafe for TreeIndex<K, V> {}
+
+impl<K, V> Pinned<'_, K, V>
+where
+    K: 'static + Clone + Ord,
+    V: 'static + Clone,
+{
+    /// Returns a reference to the value associated with the key.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use scc::TreeIndex;
+    ///
+    /// let treeindex: TreeIndex<u64, u32> = TreeIndex::new();
+    /// assert!(treeindex.insert(1, 10).is_ok());
+    ///
+    /// let mut pinned = treeindex.pinned(64);
+    /// assert_eq!(pinned.peek(&1), Some(&10));
+    /// assert!(pinned.peek(&2).is_none());
+    /// ```
+    #[inline]
+    pub fn peek<Q>(&mut self, key: &Q) -> Option<&V>
+    where
+        K: Borrow<Q>,
+        Q: Ord + ?Sized,
+    {
+        self.tick();
+        let guard = self.guard.get_or_insert_with(Guard::new);
+        self.tree.peek(key, guard)
+    }
+
+    /// Inserts a key-value pair.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error along with the supplied key-value pair if the key exists.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use scc::TreeIndex;
+    ///
+    /// let treeindex: TreeIndex<u64, u32> = TreeIndex::new();
+    ///
+    /// let mut pinned = treeindex.pinned(64);
+    /// assert!(pinned.insert(1, 10).is_ok());
+    /// assert_eq!(pinned.insert(1, 11).err().unwrap(), (1, 11));
+    /// ```
+    #[inline]
+    pub fn insert(&mut self, key: K, val: V) -> Result<(), (K, V)> {
+        self.tick();
+        let guard = self.guard.get_or_insert_with(Guard::new);
+        self.tree
+            .insert_with_guard(key, val, usize::MAX, guard)
+            .map_err(InsertError::into_entry)
+    }
+
+    /// Removes a key-value pair.
+    ///
+    /// Returns `false` if the key does not exist.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use scc::TreeIndex;
+    ///
+    /// let treeindex: TreeIndex<u64, u32> = TreeIndex::new();
+    /// assert!(treeindex.insert(1, 10).is_ok());
+    ///
+    /// let mut pinned = treeindex.pinned(64);
+    /// assert!(!pinned.remove(&2));
+    /// assert!(pinned.remove(&1));
+    /// ```
+    #[inline]
+    pub fn remove<Q>(&mut self, key: &Q) -> bool
+    where
+        K: Borrow<Q>,
+        Q: Ord + ?Sized,
+    {
+        self.tick();
+        let guard = self.guard.get_or_insert_with(Guard::new);
+        self.tree.remove_if_with_guard(key, &mut |_| true, guard)
+    }
+
+    /// Releases the [`Guard`], allowing memory retired in the meantime to be reclaimed.
+    ///
+    /// A new [`Guard`] is created by the next operation.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use scc::TreeIndex;
+    ///
+    /// let treeindex: TreeIndex<u64, u32> = TreeIndex::new();
+    ///
+    /// let mut pinned = treeindex.pinned(64);
+    /// pinned.repin();
+    /// ```
+    #[inline]
+    pub fn repin(&mut self) {
+        self.num_ops = 0;
+        self.guard.take();
+    }
+
+    /// Counts an operation, and releases the [`Guard`] if enough operations have been performed.
+    fn tick(&mut self) {
+        self.num_ops += 1;
+        if self.num_ops >= self.repin_interval {
+            self.repin();
+        }
+    }
+}
+
+impl<K, V> Debug for Pinned<'_, K, V> {
+    #[inline]
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Pinned")
+            .field("num_ops", &self.num_ops)
+            .field("repin_interval", &self.repin_interval)
+            .finish()
+    }
+}
+
+#[cfg(feature = "stream")]
+impl<K, V> Debug for Stream<'_, K, V> {
+    #[inline]
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Stream").finish()
+    }
+}
+
+#[cfg(feature = "stream")]
+impl<K, V> futures_core::Stream for Stream<'_, K, V>
+where
+    K: 'static + Clone + Ord,
+    V: 'static + Clone,
+{
+    type Item = (K, V);
+
+    #[inline]
+    fn poll_next(
+        self: Pin<&mut Self>,
+        _cx: &mut std::task::Context<'_>,
+    ) -> std::task::Poll<Option<Self::Item>> {
+        let this = self.get_mut();
+        if this.entries.is_empty() {
+            if !TreeIndex::clone_leaf_after(
+                &this.tree.root,
+                this.last_key.as_ref(),
+                &mut this.entries,
+            ) {
+                return std::task::Poll::Ready(None);
+            }
+            this.last_key = this.entries.back().map(|(k, _)| k.clone());
+        }
+        std::task::Poll::Ready(this.entries.pop_front())
+    }
+}
+
+#[cfg(feature = "stream")]
+impl<K, V> Unpin for Stream<'_, K, V> {}
+
+impl<K, V> Debug for TakeAll<K, V>
+where
+    K: 'static + Clone + Ord,
+    V: 'static + Clone,
+{
+    #[inline]
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("TakeAll")
+            .field("num_buffered", &self.entries.len())
+            .finish_non_exhaustive()
+    }
+}
+
+impl<K, V> TakeAll<K, V>
+where
+    K: 'static + Clone + Ord,
+    V: 'static + Clone,
+{
+    /// Clears the detached tree.
+    fn release(&self) {
+        if let (Some(root), _) = self.root.swap((None, Tag::None), Acquire) {
+            // Leaves are unlinked only after readers of the old tree are gone, so that they can
+            // still move on to the next leaf.
+            Guard::new().defer_execute(move || root.clear(&Guard::new()));
+        }
+    }
+}
+
+impl<K, V> Drop for TakeAll<K, V>
+where
+    K: 'static + Clone + Ord,
+    V: 'static + Clone,
+{
+    #[inline]
+    fn drop(&mut self) {
+        self.release();
+    }
+}
+
+impl<K, V> Iterator for TakeAll<K, V>
+where
+    K: 'static + Clone + Ord,
+    V: 'static + Clone,
+{
+    type Item = (K, V);
+
+    #[inline]
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.entries.is_empty() {
+            if !TreeIndex::clone_leaf_after(&self.root, self.last_key.as_ref(), &mut self.entries) {
+                self.release();
+                return None;
+            }
+            self.last_key = self.entries.back().map(|(k, _)| k.clone());
+        }
+        self.entries.pop_front()
+    }
+}
+
+impl<K, V> FusedIterator for TakeAll<K, V>
+where
+    K: 'static + Clone + Ord,
+    V: 'static + Clone,
+{
+}
+
+impl<K, V> InsertError<K, V> {
+    /// Returns the supplied key-value pair.
+    fn into_entry(self) -> (K, V) {
+        match self {
+            Self::Duplicate(k, v) | Self::Overflow(k, v) => (k, v),
+        }
+    }
+}
+
+impl<K, V> InsertBuffer<'_, K, V>
+where
+    K: 'static + Clone + Ord,
+    V: 'static + Clone,
+{
+    /// Stages a key-value pair.
     ///
-    /// Returns `None` if the key does not exist. The returned reference can survive as long as the
-    /// associated [`Guard`] is alive.
+    /// The staged entries are inserted into the [`TreeIndex`] if the buffer is full.
     ///
     /// # Examples
     ///
     /// ```
     /// use scc::TreeIndex;
-    /// use scc::ebr::Guard;
     ///
     /// let treeindex: TreeIndex<u64, u32> = TreeIndex::new();
     ///
-    /// let guard = Guard::new();
-    /// assert!(treeindex.peek(&1, &guard).is_none());
+    /// let mut buffer = treeindex.insert_buffered(2);
+    /// buffer.insert(1, 10);
+    /// assert!(treeindex.is_empty());
+    /// buffer.insert(2, 20);
+    /// assert_eq!(treeindex.len(), 2);
     /// ```
     #[inline]
-    pub fn peek<'g, Q>(&self, key: &Q, guard: &'g Guard) -> Option<&'g V>
-    where
-        K: Borrow<Q>,
-        Q: Ord + ?Sized,
-    {
-        if let Some(root_ref) = self.root.load(Acquire, guard).as_ref() {
-            return root_ref.search(key, guard);
+    pub fn insert(&mut self, key: K, val: V) {
+        self.staged.push((key, val));
+        if self.staged.len() >= self.capacity {
+            self.drain();
         }
-        None
     }
 
-    /// Peeks a key-value pair without acquiring locks.
+    /// Inserts all the staged entries into the [`TreeIndex`].
     ///
-    /// Returns `None` if the key does not exist.
+    /// Returns the entries that were rejected because their keys already existed since the last
+    /// call to [`InsertBuffer::flush`].
     ///
     /// # Examples
     ///
@@ -631,43 +4569,21 @@ where
     /// use scc::TreeIndex;
     ///
     /// let treeindex: TreeIndex<u64, u32> = TreeIndex::new();
-    /// assert!(treeindex.peek_with(&1, |k, v| *v).is_none());
-    /// ```
-    #[inline]
-    pub fn peek_with<Q, R, F: FnOnce(&Q, &V) -> R>(&self, key: &Q, reader: F) -> Option<R>
-    where
-        K: Borrow<Q>,
-        Q: Ord + ?Sized,
-    {
-        let guard = Guard::new();
-        self.peek(key, &guard).map(|v| reader(key, v))
-    }
-
-    /// Returns `true` if the [`TreeIndex`] contains the key.
-    ///
-    /// # Examples
-    ///
-    /// ```
-    /// use scc::TreeIndex;
-    ///
-    /// let treeindex: TreeIndex<u64, u32> = TreeIndex::default();
+    /// assert!(treeindex.insert(1, 10).is_ok());
     ///
-    /// assert!(!treeindex.contains(&1));
-    /// assert!(treeindex.insert(1, 0).is_ok());
-    /// assert!(treeindex.contains(&1));
+    /// let mut buffer = treeindex.insert_buffered(8);
+    /// buffer.insert(1, 11);
+    /// buffer.insert(2, 20);
+    /// assert_eq!(buffer.flush(), vec![(1, 11)]);
+    /// assert_eq!(treeindex.peek_with(&1, |_, v| *v), Some(10));
     /// ```
     #[inline]
-    pub fn contains<Q>(&self, key: &Q) -> bool
-    where
-        K: Borrow<Q>,
-        Q: Ord + ?Sized,
-    {
-        self.peek(key, &Guard::new()).is_some()
+    pub fn flush(&mut self) -> Vec<(K, V)> {
+        self.drain();
+        std::mem::take(&mut self.rejected)
     }
 
-    /// Returns the size of the [`TreeIndex`].
-    ///
-    /// It internally scans all the leaf nodes, and therefore the time complexity is O(N).
+    /// Returns the number of staged entries.
     ///
     /// # Examples
     ///
@@ -675,15 +4591,18 @@ where
     /// use scc::TreeIndex;
     ///
     /// let treeindex: TreeIndex<u64, u32> = TreeIndex::new();
-    /// assert_eq!(treeindex.len(), 0);
+    ///
+    /// let mut buffer = treeindex.insert_buffered(8);
+    /// buffer.insert(1, 10);
+    /// assert_eq!(buffer.len(), 1);
     /// ```
     #[inline]
+    #[must_use]
     pub fn len(&self) -> usize {
-        let guard = Guard::new();
-        self.iter(&guard).count()
+        self.staged.len()
     }
 
-    /// Returns `true` if the [`TreeIndex`] is empty.
+    /// Returns `true` if no entries are staged.
     ///
     /// # Examples
     ///
@@ -692,145 +4611,135 @@ where
     ///
     /// let treeindex: TreeIndex<u64, u32> = TreeIndex::new();
     ///
-    /// assert!(treeindex.is_empty());
+    /// let buffer = treeindex.insert_buffered(8);
+    /// assert!(buffer.is_empty());
     /// ```
     #[inline]
+    #[must_use]
     pub fn is_empty(&self) -> bool {
-        let guard = Guard::new();
-        !self.iter(&guard).any(|_| true)
+        self.staged.is_empty()
     }
 
-    /// Returns an [`Iter`].
-    ///
-    /// The returned [`Iter`] starts scanning from the minimum key-value pair. Key-value pairs
-    /// are scanned in ascending order, and key-value pairs that have existed since the invocation
-    /// of the method are guaranteed to be visited if they are not removed. However, it is possible
-    /// to visit removed key-value pairs momentarily.
-    ///
-    /// # Examples
-    ///
-    /// ```
-    /// use scc::TreeIndex;
-    /// use scc::ebr::Guard;
-    ///
-    /// let treeindex: TreeIndex<u64, u32> = TreeIndex::new();
-    ///
-    /// let guard = Guard::new();
-    /// let mut iter = treeindex.iter(&guard);
-    /// assert!(iter.next().is_none());
-    /// ```
-    #[inline]
-    pub fn iter<'t, 'g>(&'t self, guard: &'g Guard) -> Iter<'t, 'g, K, V> {
-        Iter::new(&self.root, guard)
-    }
+    /// Inserts the staged entries in key order, reusing the leaf that the previous entry was
+    /// inserted into as long as the next key is within the bounds of the leaf.
+    fn drain(&mut self) {
+        if self.staged.is_empty() {
+            return;
+        }
+        self.staged.sort_by(|a, b| a.0.cmp(&b.0));
 
-    /// Returns a [`Range`] that scans keys in the given range.
-    ///
-    /// Key-value pairs in the range are scanned in ascending order, and key-value pairs that have
-    /// existed since the invocation of the method are guaranteed to be visited if they are not
-    /// removed. However, it is possible to visit removed key-value pairs momentarily.
-    ///
-    /// # Examples
-    ///
-    /// ```
-    /// use scc::TreeIndex;
-    /// use scc::ebr::Guard;
-    ///
-    /// let treeindex: TreeIndex<u64, u32> = TreeIndex::new();
-    ///
-    /// let guard = Guard::new();
-    /// assert_eq!(treeindex.range(4..=8, &guard).count(), 0);
-    /// ```
-    #[inline]
-    pub fn range<'t, 'g, R: RangeBounds<K>>(
-        &'t self,
-        range: R,
-        guard: &'g Guard,
-    ) -> Range<'t, 'g, K, V, R> {
-        Range::new(&self.root, range, guard)
-    }
-}
+        let guard = Guard::new();
+        let mut cached_leaf: Option<(&Leaf<K, V>, Option<&K>)> = None;
+        for (key, val) in self.staged.drain(..) {
+            if !matches!(cached_leaf, Some((_, bound)) if bound.map_or(true, |b| key <= *b)) {
+                cached_leaf = self
+                    .tree
+                    .root
+                    .load(Acquire, &guard)
+                    .as_ref()
+                    .and_then(|root_ref| root_ref.search_leaf(&key, None, &guard));
+            }
 
-impl<K, V> Clone for TreeIndex<K, V>
-where
-    K: 'static + Clone + Ord,
-    V: 'static + Clone,
-{
-    #[inline]
-    fn clone(&self) -> Self {
-        let self_clone = Self::default();
-        for (k, v) in self.iter(&Guard::new()) {
-            let _reuslt = self_clone.insert(k.clone(), v.clone());
+            // Keys are sorted, therefore the key is not smaller than any key previously inserted
+            // into the cached leaf, and the leaf cannot accommodate it only if the leaf is full or
+            // being split or removed.
+            let (key, val) = match cached_leaf {
+                Some((leaf, _)) => match leaf.insert(key, val) {
+                    InsertResult::Success => continue,
+                    InsertResult::Duplicate(k, v) => {
+                        self.rejected.push((k, v));
+                        continue;
+                    }
+                    InsertResult::Full(k, v)
+                    | InsertResult::Frozen(k, v)
+                    | InsertResult::Retired(k, v)
+                    | InsertResult::Retry(k, v) => (k, v),
+                },
+                None => (key, val),
+            };
+            cached_leaf = None;
+            if let Err(entry) = self.tree.insert(key, val) {
+                self.rejected.push(entry);
+            }
         }
-        self_clone
     }
 }
 
-impl<K, V> Debug for TreeIndex<K, V>
+impl<K, V> Debug for InsertBuffer<'_, K, V>
 where
-    K: 'static + Clone + Debug + Ord,
-    V: 'static + Clone + Debug,
+    K: 'static + Clone + Ord,
+    V: 'static + Clone,
 {
     #[inline]
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        let guard = Guard::new();
-        f.debug_map().entries(self.iter(&guard)).finish()
-    }
-}
-
-impl<K, V> Default for TreeIndex<K, V> {
-    /// Creates a [`TreeIndex`] with the default parameters.
-    ///
-    /// # Examples
-    ///
-    /// ```
-    /// use scc::TreeIndex;
-    ///
-    /// let treeindex: TreeIndex<u64, u32> = TreeIndex::default();
-    /// ```
-    #[inline]
-    fn default() -> Self {
-        Self::new()
-    }
-}
-
-impl<K, V> Drop for TreeIndex<K, V> {
-    #[inline]
-    fn drop(&mut self) {
-        self.clear();
+        f.debug_struct("InsertBuffer")
+            .field("len", &self.staged.len())
+            .field("capacity", &self.capacity)
+            .finish()
     }
 }
 
-impl<K, V> PartialEq for TreeIndex<K, V>
+impl<K, V> Drop for InsertBuffer<'_, K, V>
 where
     K: 'static + Clone + Ord,
-    V: 'static + Clone + PartialEq,
+    V: 'static + Clone,
 {
     #[inline]
-    fn eq(&self, other: &Self) -> bool {
-        // The key order is preserved, therefore comparing iterators suffices.
-        let guard = Guard::new();
-        Iterator::eq(self.iter(&guard), other.iter(&guard))
+    fn drop(&mut self) {
+        self.drain();
     }
 }
 
-impl<K, V> UnwindSafe for TreeIndex<K, V> {}
-
 impl<'t, 'g, K, V> Iter<'t, 'g, K, V> {
     #[inline]
-    fn new(root: &'t AtomicShared<Node<K, V>>, guard: &'g Guard) -> Iter<'t, 'g, K, V> {
+    fn new(tree: &'t TreeIndex<K, V>, guard: &'g Guard) -> Iter<'t, 'g, K, V> {
         Iter::<'t, 'g, K, V> {
-            root,
+            root: &tree.root,
+            num_replacements: &tree.num_replacements,
+            num_replacements_at_start: tree.num_replacements.load(Acquire),
             leaf_scanner: None,
             guard,
         }
     }
+
+    /// Returns `true` if the contents of the [`TreeIndex`] were replaced after the [`Iter`] was
+    /// created.
+    ///
+    /// The [`Iter`] keeps scanning the old entries if the [`TreeIndex`] was cleared or replaced
+    /// by [`TreeIndex::clear`], [`TreeIndex::replace_all`], [`TreeIndex::rebuild_from_leaves`],
+    /// or [`TreeIndex::split_off`] in the meantime, and a long scan may restart with a new
+    /// [`Iter`] to see the current entries. Splits and merges of nodes do not count as
+    /// replacements.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use scc::TreeIndex;
+    /// use scc::ebr::Guard;
+    ///
+    /// let treeindex: TreeIndex<u64, u32> = TreeIndex::new();
+    ///
+    /// assert!(treeindex.insert(1, 10).is_ok());
+    ///
+    /// let guard = Guard::new();
+    /// let mut iter = treeindex.iter(&guard);
+    /// assert_eq!(iter.next(), Some((&1, &10)));
+    /// assert!(!iter.was_replaced());
+    ///
+    /// treeindex.replace_all([(2, 20)]);
+    /// assert!(iter.was_replaced());
+    /// ```
+    #[inline]
+    #[must_use]
+    pub fn was_replaced(&self) -> bool {
+        self.num_replacements.load(Acquire) != self.num_replacements_at_start
+    }
 }
 
 impl<'t, 'g, K, V> Debug for Iter<'t, 'g, K, V> {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         f.debug_struct("Iter")
-            .field("root", &self.root)
+            .field("root", &self.root.load(Acquire, &Guard::new()).as_ptr())
             .field("leaf_scanner", &self.leaf_scanner)
             .finish()
     }
@@ -909,6 +4818,52 @@ where
     V: 'static + Clone,
     R: RangeBounds<K>,
 {
+    /// Returns a [`ScanToken`] from which the scan can be resumed.
+    ///
+    /// Returns `None` if the scan has finished, i.e., all the entries in the range were visited.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use scc::TreeIndex;
+    /// use scc::ebr::Guard;
+    ///
+    /// let treeindex: TreeIndex<u64, u32> = TreeIndex::new();
+    ///
+    /// assert!(treeindex.insert(1, 0).is_ok());
+    /// assert!(treeindex.insert(2, 0).is_ok());
+    ///
+    /// let guard = Guard::new();
+    /// let mut range = treeindex.range(..2, &guard);
+    /// assert!(range.token().is_some());
+    /// assert_eq!(range.next(), Some((&1, &0)));
+    /// assert!(range.token().is_some());
+    /// assert!(range.next().is_none());
+    /// assert!(range.token().is_none());
+    /// ```
+    #[inline]
+    pub fn token(&self) -> Option<ScanToken<K>> {
+        let end = self.range.end_bound().cloned();
+        if let Some((k, _)) = self.leaf_scanner.as_ref().and_then(Scanner::get) {
+            if !self.range.contains(k) {
+                // The scanner stops at the first key beyond the range.
+                return None;
+            }
+            return Some(ScanToken {
+                start: Excluded(k.clone()),
+                end,
+            });
+        } else if !self.check_lower_bound {
+            return None;
+        }
+
+        // Either the scan has not started yet, or it has finished without finding any entries in
+        // the range.
+        let start = self.range.start_bound().cloned();
+        Range::new(self.root, (start.clone(), end.clone()), self.guard).next()?;
+        Some(ScanToken { start, end })
+    }
+
     #[inline]
     fn next_unbounded(&mut self) -> Option<(&'g K, &'g V)> {
         if self.leaf_scanner.is_none() {
@@ -984,7 +4939,7 @@ impl<'t, 'g, K, V, R: RangeBounds<K>> Debug for Range<'t, 'g, K, V, R> {
     #[inline]
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         f.debug_struct("Range")
-            .field("root", &self.root)
+            .field("root", &self.root.load(Acquire, &Guard::new()).as_ptr())
             .field("leaf_scanner", &self.leaf_scanner)
             .field("check_lower_bound", &self.check_lower_bound)
             .field("check_upper_bound", &self.check_upper_bound)
@@ -1052,3 +5007,78 @@ where
 }
 
 impl<'t, 'g, K, V, R> UnwindSafe for Range<'t, 'g, K, V, R> where R: RangeBounds<K> + UnwindSafe {}
+
+/// The first component of a pair that is compared with the keys of a [`TreeIndex`] keyed by
+/// pairs.
+///
+/// A pair borrowed as [`FirstOf`] is only compared by its first component, therefore it is
+/// equal to every pair with the same first component; it is only used to seek a position before
+/// those pairs.
+#[derive(Eq, Ord, PartialEq, PartialOrd)]
+#[repr(transparent)]
+struct FirstOf<A>(A);
+
+impl<A> FirstOf<A> {
+    /// Converts a reference to the first component.
+    #[inline]
+    fn from_ref(a: &A) -> &FirstOf<A> {
+        // Safety: `FirstOf` is a transparent wrapper.
+        unsafe { &*ptr::addr_of!(*a).cast::<FirstOf<A>>() }
+    }
+}
+
+impl<A, B> Borrow<FirstOf<A>> for (A, B) {
+    #[inline]
+    fn borrow(&self) -> &FirstOf<A> {
+        FirstOf::from_ref(&self.0)
+    }
+}
+
+/// 64-bit FNV-1a [`Hasher`] that yields the same hash values on every platform.
+struct Fnv1aHasher(u64);
+
+impl Default for Fnv1aHasher {
+    #[inline]
+    fn default() -> Self {
+        Self(0xCBF2_9CE4_8422_2325)
+    }
+}
+
+impl Hasher for Fnv1aHasher {
+    #[inline]
+    fn finish(&self) -> u64 {
+        self.0
+    }
+
+    #[inline]
+    fn write(&mut self, bytes: &[u8]) {
+        for byte in bytes {
+            self.0 = (self.0 ^ u64::from(*byte)).wrapping_mul(0x0000_0100_0000_01B3);
+        }
+    }
+
+    #[inline]
+    fn write_u16(&mut self, i: u16) {
+        self.write(&i.to_le_bytes());
+    }
+
+    #[inline]
+    fn write_u32(&mut self, i: u32) {
+        self.write(&i.to_le_bytes());
+    }
+
+    #[inline]
+    fn write_u64(&mut self, i: u64) {
+        self.write(&i.to_le_bytes());
+    }
+
+    #[inline]
+    fn write_u128(&mut self, i: u128) {
+        self.write(&i.to_le_bytes());
+    }
+
+    #[inline]
+    fn write_usize(&mut self, i: usize) {
+        self.write_u64(i as u64);
+    }
+}
@@ -1,4 +1,4 @@
-use super::leaf::{InsertResult, Leaf, RemoveResult, Scanner, DIMENSION};
+use super::leaf::{InsertResult, Leaf, RemoveResult, Scanner, UpdateResult, DIMENSION};
 use super::leaf_node::RemoveRangeState;
 use super::leaf_node::{LOCKED, RETIRED};
 use super::node::Node;
@@ -8,8 +8,9 @@ use crate::maybe_std::AtomicU8;
 use crate::wait_queue::{DeriveAsyncWait, WaitQueue};
 use std::borrow::Borrow;
 use std::cmp::Ordering::{Equal, Greater, Less};
+use std::fmt::{self, Debug};
 use std::mem::forget;
-use std::ops::RangeBounds;
+use std::ops::{Bound, RangeBounds};
 use std::ptr;
 use std::sync::atomic::AtomicPtr;
 use std::sync::atomic::Ordering::{AcqRel, Acquire, Relaxed, Release};
@@ -93,6 +94,84 @@ impl<K, V> InternalNode<K, V> {
         depth
     }
 
+    /// Visits every [`Leaf`] along with the child indexes from the node to the [`Leaf`].
+    #[inline]
+    pub(super) fn for_each_leaf<'g, F: FnMut(&[usize], &'g Leaf<K, V>)>(
+        &self,
+        path: &mut Vec<usize>,
+        f: &mut F,
+        guard: &'g Guard,
+    ) where
+        K: 'g,
+        V: 'g,
+    {
+        let mut index = 0;
+        for (_, child) in Scanner::new(&self.children) {
+            if let Some(child) = child.load(Acquire, guard).as_ref() {
+                path.push(index);
+                child.for_each_leaf(path, f, guard);
+                path.pop();
+            }
+            index += 1;
+        }
+        if let Some(unbounded) = self.unbounded_child.load(Acquire, guard).as_ref() {
+            path.push(index);
+            unbounded.for_each_leaf(path, f, guard);
+            path.pop();
+        }
+    }
+
+    /// Checks if the [`InternalNode`] has the same separators and children as the other one.
+    #[inline]
+    pub(super) fn structurally_eq(&self, other: &Self, guard: &Guard) -> bool
+    where
+        K: PartialEq,
+        V: PartialEq,
+    {
+        let child_eq = |c: &AtomicShared<Node<K, V>>, o: &AtomicShared<Node<K, V>>| match (
+            c.load(Acquire, guard).as_ref(),
+            o.load(Acquire, guard).as_ref(),
+        ) {
+            (Some(c), Some(o)) => c.structurally_eq(o, guard),
+            (None, None) => true,
+            _ => false,
+        };
+        let mut scanner = Scanner::new(&self.children);
+        let mut other_scanner = Scanner::new(&other.children);
+        loop {
+            match (scanner.next(), other_scanner.next()) {
+                (Some((key, child)), Some((other_key, other_child))) => {
+                    if key != other_key || !child_eq(child, other_child) {
+                        return false;
+                    }
+                }
+                (None, None) => break,
+                _ => return false,
+            }
+        }
+        child_eq(&self.unbounded_child, &other.unbounded_child)
+    }
+
+    /// Returns the separator keys, and pushes the child nodes into `child_nodes`.
+    #[inline]
+    pub(super) fn children<'g>(
+        &'g self,
+        child_nodes: &mut Vec<&'g Node<K, V>>,
+        guard: &'g Guard,
+    ) -> Vec<&'g K> {
+        let mut separators = Vec::new();
+        for (key, child) in Scanner::new(&self.children) {
+            separators.push(key);
+            if let Some(child) = child.load(Acquire, guard).as_ref() {
+                child_nodes.push(child);
+            }
+        }
+        if let Some(unbounded) = self.unbounded_child.load(Acquire, guard).as_ref() {
+            child_nodes.push(unbounded);
+        }
+        separators
+    }
+
     /// Returns `true` if the [`InternalNode`] has retired.
     #[inline]
     pub(super) fn retired(&self) -> bool {
@@ -173,6 +252,60 @@ where
         }
     }
 
+    /// Returns the number of nodes and the number of key comparisons that searching for the key
+    /// goes through.
+    #[inline]
+    pub(super) fn descent_profile<Q>(&self, key: &Q, guard: &Guard) -> (usize, usize)
+    where
+        K: Borrow<Q>,
+        Q: Ord + ?Sized,
+    {
+        let num_comparisons = self.children.num_comparisons(key);
+        let child = match self.children.min_greater_equal(key).0 {
+            Some((_, child)) => child.load(Acquire, guard),
+            None => self.unbounded_child.load(Acquire, guard),
+        };
+        let (num_nodes, num_child_comparisons) = child
+            .as_ref()
+            .map_or((0, 0), |child| child.descent_profile(key, guard));
+        (num_nodes + 1, num_comparisons + num_child_comparisons)
+    }
+
+    /// Returns the leaf that the key belongs to along with the inclusive upper bound of the keys
+    /// that the leaf can contain.
+    #[inline]
+    pub(super) fn search_leaf<'g, Q>(
+        &'g self,
+        key: &Q,
+        bound: Option<&'g K>,
+        guard: &'g Guard,
+    ) -> Option<(&'g Leaf<K, V>, Option<&'g K>)>
+    where
+        K: Borrow<Q>,
+        Q: Ord + ?Sized,
+    {
+        loop {
+            let (child, metadata) = self.children.min_greater_equal(key);
+            if let Some((child_key, child)) = child {
+                if let Some(child) = child.load(Acquire, guard).as_ref() {
+                    if self.children.validate(metadata) {
+                        // Data race resolution - see `LeafNode::search`.
+                        return child.search_leaf(key, Some(child_key), guard);
+                    }
+                }
+            } else {
+                let unbounded_ptr = self.unbounded_child.load(Acquire, guard);
+                if let Some(unbounded) = unbounded_ptr.as_ref() {
+                    if self.children.validate(metadata) {
+                        return unbounded.search_leaf(key, bound, guard);
+                    }
+                } else {
+                    return None;
+                }
+            }
+        }
+    }
+
     /// Returns the minimum key entry.
     #[inline]
     pub(super) fn min<'g>(&self, guard: &'g Guard) -> Option<Scanner<'g, K, V>> {
@@ -437,6 +570,109 @@ where
         }
     }
 
+    /// Updates the value associated with the given key.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if a retry is required.
+    #[inline]
+    pub(super) fn update<Q, F: FnMut(&V) -> Option<V>, D: DeriveAsyncWait>(
+        &self,
+        key: &Q,
+        updater: &mut F,
+        async_wait: &mut D,
+        guard: &Guard,
+    ) -> Result<UpdateResult<K, V>, ()>
+    where
+        K: Borrow<Q>,
+        Q: Ord + ?Sized,
+    {
+        loop {
+            let (child, metadata) = self.children.min_greater_equal(key);
+            if let Some((child_key, child)) = child {
+                let child_ptr = child.load(Acquire, guard);
+                if let Some(child_ref) = child_ptr.as_ref() {
+                    if self.children.validate(metadata) {
+                        // Data race resolution - see `LeafNode::search`.
+                        match child_ref.update(key, updater, async_wait, guard)? {
+                            UpdateResult::Full(k, v) => {
+                                match self.split_node(
+                                    k,
+                                    v,
+                                    Some(child_key),
+                                    child_ptr,
+                                    child,
+                                    false,
+                                    async_wait,
+                                    guard,
+                                ) {
+                                    Ok(InsertResult::Retry(..)) => continue,
+                                    Ok(InsertResult::Full(k, v)) => {
+                                        return Ok(UpdateResult::Full(k, v))
+                                    }
+                                    Ok(InsertResult::Retired(..)) => {
+                                        return Ok(UpdateResult::Retired)
+                                    }
+                                    _ => return Err(()),
+                                }
+                            }
+                            UpdateResult::Retired => {
+                                debug_assert!(child_ref.retired());
+                                if self.coalesce(guard) == RemoveResult::Retired {
+                                    debug_assert!(self.retired());
+                                    return Ok(UpdateResult::Retired);
+                                }
+                                return Err(());
+                            }
+                            result => return Ok(result),
+                        }
+                    }
+                }
+                // It is not a hot loop - see `LeafNode::search`.
+                continue;
+            }
+
+            let unbounded_ptr = self.unbounded_child.load(Acquire, guard);
+            if let Some(unbounded) = unbounded_ptr.as_ref() {
+                debug_assert!(unbounded_ptr.tag() == Tag::None);
+                if !self.children.validate(metadata) {
+                    // Data race resolution - see `LeafNode::search`.
+                    continue;
+                }
+                match unbounded.update(key, updater, async_wait, guard)? {
+                    UpdateResult::Full(k, v) => {
+                        match self.split_node(
+                            k,
+                            v,
+                            None,
+                            unbounded_ptr,
+                            &self.unbounded_child,
+                            false,
+                            async_wait,
+                            guard,
+                        ) {
+                            Ok(InsertResult::Retry(..)) => continue,
+                            Ok(InsertResult::Full(k, v)) => return Ok(UpdateResult::Full(k, v)),
+                            Ok(InsertResult::Retired(..)) => return Ok(UpdateResult::Retired),
+                            _ => return Err(()),
+                        }
+                    }
+                    UpdateResult::Retired => {
+                        debug_assert!(unbounded.retired());
+                        if self.coalesce(guard) == RemoveResult::Retired {
+                            debug_assert!(self.retired());
+                            return Ok(UpdateResult::Retired);
+                        }
+                        return Err(());
+                    }
+                    result => return Ok(result),
+                }
+            }
+            debug_assert!(unbounded_ptr.tag() == RETIRED);
+            return Ok(UpdateResult::Retired);
+        }
+    }
+
     /// Removes a range of entries.
     ///
     /// Returns the number of remaining children.
@@ -578,6 +814,56 @@ where
         Ok(num_children)
     }
 
+    /// Estimates the number of entries contained in the range and the total number of entries.
+    ///
+    /// Children fully contained in the range or disjoint from the range are not scanned, but
+    /// their sizes are estimated by [`Node::estimate_len`].
+    #[inline]
+    pub(super) fn estimate<R: RangeBounds<K>>(&self, range: &R, guard: &Guard) -> (f64, f64) {
+        let start_unbounded = matches!(range.start_bound(), Bound::Unbounded);
+        let mut current_state = RemoveRangeState::Below;
+        let mut num_contained = 0.0;
+        let mut num_entries = 0.0;
+        for (key, child) in Scanner::new(&self.children) {
+            let Some(child) = child.load(Acquire, guard).as_ref() else {
+                continue;
+            };
+            if current_state != RemoveRangeState::MaybeAbove {
+                current_state = current_state.next(key, range, start_unbounded);
+                if matches!(
+                    current_state,
+                    RemoveRangeState::MaybeBelow | RemoveRangeState::MaybeAbove
+                ) {
+                    let (contained, len) = child.estimate(range, guard);
+                    num_contained += contained;
+                    num_entries += len;
+                    continue;
+                }
+            }
+            let len = child.estimate_len(guard);
+            if current_state == RemoveRangeState::FullyContained {
+                num_contained += len;
+            }
+            num_entries += len;
+        }
+        if let Some(unbounded) = self.unbounded_child.load(Acquire, guard).as_ref() {
+            if current_state == RemoveRangeState::MaybeAbove {
+                num_entries += unbounded.estimate_len(guard);
+            } else {
+                let (contained, len) = unbounded.estimate(range, guard);
+                num_contained += contained;
+                num_entries += len;
+            }
+        }
+        (num_contained, num_entries)
+    }
+
+    /// Estimates the number of entries in the [`InternalNode`].
+    #[inline]
+    pub(super) fn estimate_len(&self, guard: &Guard) -> f64 {
+        self.estimate(&(..), guard).1
+    }
+
     /// Splits a full node.
     ///
     /// # Errors
@@ -1061,6 +1347,47 @@ where
         }
         false
     }
+
+    /// Reverses the order of the separators in the [`InternalNode`] and its descendants.
+    #[cfg(test)]
+    pub(super) fn scramble_separators(&self, guard: &Guard) {
+        for (_, child) in Scanner::new(&self.children) {
+            if let Some(child) = child.load(Acquire, guard).as_ref() {
+                child.scramble_separators(guard);
+            }
+        }
+        if let Some(unbounded) = self.unbounded_child.load(Acquire, guard).as_ref() {
+            unbounded.scramble_separators(guard);
+        }
+        self.children.reverse_keys();
+    }
+
+    /// Checks if the keys in the [`InternalNode`] are in `(lower, upper]` and the separators
+    /// bound the keys in the children.
+    #[cfg(test)]
+    pub(super) fn validate<'g>(
+        &'g self,
+        mut lower: Option<&'g K>,
+        upper: Option<&'g K>,
+        guard: &'g Guard,
+    ) -> bool {
+        for (key, child) in Scanner::new(&self.children) {
+            if lower.map_or(false, |lower| lower >= key) || upper.map_or(false, |upper| key > upper)
+            {
+                return false;
+            }
+            if let Some(child) = child.load(Acquire, guard).as_ref() {
+                if !child.validate(lower, Some(key), guard) {
+                    return false;
+                }
+            }
+            lower.replace(key);
+        }
+        self.unbounded_child
+            .load(Acquire, guard)
+            .as_ref()
+            .map_or(true, |unbounded| unbounded.validate(lower, upper, guard))
+    }
 }
 
 impl<'n, K, V> Locker<'n, K, V> {
@@ -1088,6 +1415,34 @@ impl<'n, K, V> Drop for Locker<'n, K, V> {
     }
 }
 
+impl<K: Debug, V> Debug for InternalNode<K, V> {
+    /// Formats the separator keys and the children, and whether a split is in progress.
+    ///
+    /// Children are formatted recursively, so that `{:#?}` indents each level of the sub-tree.
+    #[inline]
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let guard = Guard::new();
+        let children = Scanner::new(&self.children)
+            .filter_map(|(k, child)| child.load(Acquire, &guard).as_ref().map(|child| (k, child)))
+            .collect::<Vec<_>>();
+        f.debug_struct("InternalNode")
+            .field("children", &children)
+            .field(
+                "unbounded_child",
+                &self.unbounded_child.load(Acquire, &guard).as_ref(),
+            )
+            .field(
+                "low_key_node",
+                &!self.split_op.low_key_node.is_null(Relaxed),
+            )
+            .field(
+                "high_key_node",
+                &!self.split_op.high_key_node.is_null(Relaxed),
+            )
+            .finish_non_exhaustive()
+    }
+}
+
 impl<K, V> StructuralChange<K, V> {
     fn reset(&self) -> Option<Shared<Node<K, V>>> {
         self.origin_node_key.store(ptr::null_mut(), Relaxed);
@@ -1134,6 +1489,79 @@ mod test {
         }
     }
 
+    #[test]
+    fn ensure_balanced() {
+        // The bounded child is a `LeafNode`, whereas the unbounded child is a level 2 node.
+        let separator = 16;
+        let internal_node = new_level_3_node();
+        assert!(matches!(
+            internal_node
+                .children
+                .insert(separator, AtomicShared::new(Node::new_leaf_node())),
+            InsertResult::Success
+        ));
+        let guard = Guard::new();
+        for k in 0..separator * 2 {
+            assert!(matches!(
+                internal_node.insert(k, k, &mut (), &guard),
+                Ok(InsertResult::Success)
+            ));
+        }
+
+        let tree = crate::TreeIndex::new();
+        tree.root.swap(
+            (Some(Shared::new(Node::Internal(internal_node))), Tag::None),
+            Release,
+        );
+        let mut leaf_depths = Vec::new();
+        if let Some(root_ref) = tree.root.load(Acquire, &guard).as_ref() {
+            root_ref.for_each_leaf(
+                &mut Vec::new(),
+                &mut |p, _| leaf_depths.push(p.len()),
+                &guard,
+            );
+        }
+        assert!(leaf_depths.contains(&2) && leaf_depths.contains(&3));
+
+        assert!(tree.ensure_balanced());
+        assert!(!tree.ensure_balanced());
+        assert!(tree
+            .iter(&guard)
+            .map(|(k, v)| (*k, *v))
+            .eq((0..separator * 2).map(|k| (k, k))));
+    }
+
+    #[test]
+    fn debug() {
+        let separator = 16;
+        let internal_node = new_level_3_node();
+        assert!(matches!(
+            internal_node
+                .children
+                .insert(separator, AtomicShared::new(Node::new_leaf_node())),
+            InsertResult::Success
+        ));
+        let guard = Guard::new();
+        for k in 0..separator * 2 {
+            assert!(matches!(
+                internal_node.insert(k, k, &mut (), &guard),
+                Ok(InsertResult::Success)
+            ));
+        }
+
+        let node = Node::Internal(internal_node);
+        let output = format!("{node:#?}");
+        assert!(output.starts_with("InternalNode {"));
+        assert_eq!(output.matches("InternalNode {").count(), 2);
+        assert_eq!(output.matches("LeafNode {").count(), 2);
+        assert!(output.contains("unbounded_child: true"));
+        assert!(!output.contains("low_key_node: true"));
+        assert!(!output.contains("high_key_leaf: true"));
+        let output = format!("{node:?}");
+        assert_eq!(output.lines().count(), 1);
+        assert!(output.ends_with(".. }"));
+    }
+
     #[test]
     fn bulk() {
         let internal_node = new_level_3_node();
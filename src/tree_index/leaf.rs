@@ -83,6 +83,24 @@ pub enum RemoveResult {
     Frozen,
 }
 
+/// The result of update.
+pub enum UpdateResult<K, V> {
+    /// Update succeeded.
+    Success,
+
+    /// The key does not exist, or no new value was supplied.
+    Fail,
+
+    /// No vacant slot for the updated entry.
+    Full(K, V),
+
+    /// The [`Leaf`] is frozen.
+    Frozen,
+
+    /// The node containing the [`Leaf`] has retired.
+    Retired,
+}
+
 impl<K, V> Leaf<K, V> {
     /// Creates a new [`Leaf`].
     #[cfg(not(feature = "loom"))]
@@ -173,6 +191,23 @@ impl<K, V> Leaf<K, V> {
         None
     }
 
+    /// Returns the initialized entries in slot order, representing removed entries as `None`.
+    #[cfg(debug_assertions)]
+    #[inline]
+    pub(super) fn physical_entries(&self) -> impl Iterator<Item = Option<(&K, &V)>> {
+        let metadata = self.metadata.load(Acquire);
+        (0..DIMENSION.num_entries).filter_map(move |i| {
+            let rank = DIMENSION.rank(metadata, i);
+            if rank == Dimension::uninit_rank() {
+                None
+            } else if rank == DIMENSION.removed_rank() {
+                Some(None)
+            } else {
+                Some(Some((self.key_at(i), self.value_at(i))))
+            }
+        })
+    }
+
     /// Inserts a key value pair at the specified position without checking the metadata.
     ///
     /// `rank` is calculated as `index + 1`.
@@ -459,6 +494,64 @@ where
         RemoveResult::Fail
     }
 
+    /// Replaces the value associated with the key if `updater` returns a new value.
+    ///
+    /// The new entry is written to a vacant slot, and the slot takes over the rank of the old
+    /// entry in a single metadata update; readers observe either the old or the new value.
+    #[inline]
+    pub(super) fn update<Q, F: FnMut(&V) -> Option<V>>(
+        &self,
+        key: &Q,
+        updater: &mut F,
+    ) -> UpdateResult<K, V>
+    where
+        K: Borrow<Q>,
+        Q: Ord + ?Sized,
+    {
+        let mut metadata = self.metadata.load(Acquire);
+        'after_read_metadata: loop {
+            if Dimension::frozen(metadata) {
+                return UpdateResult::Frozen;
+            }
+            let Some(index) = self.search_slot(key, metadata) else {
+                return UpdateResult::Fail;
+            };
+            let Some(val) = updater(self.value_at(index)) else {
+                return UpdateResult::Fail;
+            };
+
+            let mut mutable_metadata = metadata;
+            for i in 0..DIMENSION.num_entries {
+                let rank = mutable_metadata % (1_usize << DIMENSION.num_bits_per_entry);
+                if rank == Dimension::uninit_rank() {
+                    let interim_metadata = DIMENSION.augment(metadata, i, DIMENSION.removed_rank());
+
+                    // Reserve the slot.
+                    if let Err(actual) =
+                        self.metadata
+                            .compare_exchange(metadata, interim_metadata, Acquire, Acquire)
+                    {
+                        metadata = actual;
+                        continue 'after_read_metadata;
+                    }
+
+                    self.write(i, self.key_at(index).clone(), val);
+                    match self.post_update(index, i, interim_metadata) {
+                        Ok(()) => return UpdateResult::Success,
+                        Err(actual) => {
+                            // The old entry has been removed, or the `Leaf` is being split.
+                            metadata = actual;
+                            continue 'after_read_metadata;
+                        }
+                    }
+                }
+                mutable_metadata >>= DIMENSION.num_bits_per_entry;
+            }
+
+            return UpdateResult::Full(self.key_at(index).clone(), val);
+        }
+    }
+
     /// Removes a range of entries.
     ///
     /// Returns the number of remaining children.
@@ -480,6 +573,20 @@ where
         }
     }
 
+    /// Counts the number of entries contained in the range and the total number of entries.
+    #[inline]
+    pub(super) fn count<R: RangeBounds<K>>(&self, range: &R) -> (u32, u32) {
+        let mut num_contained = 0;
+        let mut num_entries = 0;
+        for (k, _) in Scanner::new(self) {
+            num_entries += 1;
+            if range.contains(k) {
+                num_contained += 1;
+            }
+        }
+        (num_contained, num_entries)
+    }
+
     /// Returns a value associated with the key.
     #[inline]
     pub(super) fn search<Q>(&self, key: &Q) -> Option<&V>
@@ -491,6 +598,35 @@ where
         self.search_slot(key, metadata).map(|i| self.value_at(i))
     }
 
+    /// Returns the number of key comparisons that searching for the key performs.
+    #[inline]
+    pub(super) fn num_comparisons<Q>(&self, key: &Q) -> usize
+    where
+        K: Borrow<Q>,
+        Q: Ord + ?Sized,
+    {
+        let mut num_comparisons = 0;
+        let mut min_max_rank = DIMENSION.removed_rank();
+        let mut max_min_rank = 0;
+        let mut mutable_metadata = self.metadata.load(Acquire);
+        for i in 0..DIMENSION.num_entries {
+            if mutable_metadata == 0 {
+                break;
+            }
+            let rank = mutable_metadata % (1_usize << DIMENSION.num_bits_per_entry);
+            if rank < min_max_rank && rank > max_min_rank {
+                num_comparisons += 1;
+                match self.compare(i, key) {
+                    Ordering::Less => max_min_rank = rank,
+                    Ordering::Greater => min_max_rank = rank,
+                    Ordering::Equal => break,
+                }
+            }
+            mutable_metadata >>= DIMENSION.num_bits_per_entry;
+        }
+        num_comparisons
+    }
+
     /// Returns the index of the key-value pair that is smaller than the given key.
     #[inline]
     pub(super) fn max_less<Q>(&self, mut mutable_metadata: usize, key: &Q) -> usize
@@ -671,6 +807,43 @@ where
         }
     }
 
+    /// Post-processing after reserving a free slot for an updated entry.
+    ///
+    /// Returns the latest metadata if the slot had to be rolled back.
+    fn post_update(
+        &self,
+        old_slot_index: usize,
+        new_slot_index: usize,
+        mut prev_metadata: usize,
+    ) -> Result<(), usize> {
+        loop {
+            let rank = DIMENSION.rank(prev_metadata, old_slot_index);
+            if rank == DIMENSION.removed_rank() || Dimension::frozen(prev_metadata) {
+                drop(self.take(new_slot_index));
+                let result = self
+                    .metadata
+                    .fetch_and(!DIMENSION.rank_mask(new_slot_index), Release)
+                    & (!DIMENSION.rank_mask(new_slot_index));
+                return Err(result);
+            }
+
+            // Make the new entry reachable and the old one unreachable at once.
+            let new_metadata = DIMENSION.augment(
+                DIMENSION.augment(prev_metadata, old_slot_index, DIMENSION.removed_rank()),
+                new_slot_index,
+                rank,
+            );
+            if let Err(actual) =
+                self.metadata
+                    .compare_exchange(prev_metadata, new_metadata, AcqRel, Acquire)
+            {
+                prev_metadata = actual;
+                continue;
+            }
+            return Ok(());
+        }
+    }
+
     /// Searches for a slot in which the key is stored.
     fn search_slot<Q>(&self, key: &Q, mut mutable_metadata: usize) -> Option<usize>
     where
@@ -713,6 +886,29 @@ where
     {
         self.key_at(index).borrow().cmp(key)
     }
+
+    /// Reverses the order of the keys without updating the metadata, which corrupts the
+    /// [`Leaf`].
+    #[cfg(test)]
+    pub(super) fn reverse_keys(&self) {
+        let metadata = self.metadata.load(Acquire);
+        let mut indexes = Vec::new();
+        let mut index = DIMENSION.num_entries;
+        loop {
+            index = Self::next(index, metadata);
+            if index == DIMENSION.num_entries {
+                break;
+            }
+            indexes.push(index);
+        }
+        for i in 0..indexes.len() / 2 {
+            unsafe {
+                (*self.entry_array.get())
+                    .0
+                    .swap(indexes[i], indexes[indexes.len() - 1 - i]);
+            }
+        }
+    }
 }
 
 impl<K, V> Drop for Leaf<K, V> {
@@ -882,6 +1078,12 @@ impl<'l, K, V> Scanner<'l, K, V> {
         self.leaf.max_key()
     }
 
+    /// Returns a reference to the [`Leaf`] that the [`Scanner`] is scanning.
+    #[inline]
+    pub(super) const fn leaf(&self) -> &'l Leaf<K, V> {
+        self.leaf
+    }
+
     /// Traverses the linked list.
     #[inline]
     pub(super) fn jump<'g, Q>(
@@ -1064,6 +1266,39 @@ mod test {
         ));
     }
 
+    #[test]
+    fn replace() {
+        let leaf: Leaf<usize, usize> = Leaf::new();
+        for i in 0..DIMENSION.num_entries / 2 {
+            assert!(matches!(leaf.insert(i, i), InsertResult::Success));
+        }
+        for i in 0..DIMENSION.num_entries / 2 {
+            assert!(
+                matches!(
+                    leaf.update(&i, &mut |v| (*v % 2 == 0).then_some(v + 1)),
+                    UpdateResult::Success
+                ) == (i % 2 == 0)
+            );
+        }
+        assert!(matches!(
+            leaf.update(&DIMENSION.num_entries, &mut |v| Some(*v)),
+            UpdateResult::Fail
+        ));
+
+        let scanner = Scanner::new(&leaf);
+        for (i, (k, v)) in scanner.enumerate() {
+            assert_eq!(i, *k);
+            assert_eq!(*v, if i % 2 == 0 { i + 1 } else { i });
+        }
+
+        while matches!(leaf.update(&0, &mut |v| Some(v + 1)), UpdateResult::Success) {}
+        assert!(matches!(
+            leaf.update(&0, &mut |v| Some(v + 1)),
+            UpdateResult::Full(0, _)
+        ));
+        assert_eq!(Scanner::new(&leaf).count(), DIMENSION.num_entries / 2);
+    }
+
     #[test]
     fn calculate_boundary() {
         let leaf: Leaf<usize, usize> = Leaf::new();
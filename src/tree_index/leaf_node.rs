@@ -1,4 +1,4 @@
-use super::leaf::{InsertResult, RemoveResult, Scanner, DIMENSION};
+use super::leaf::{InsertResult, RemoveResult, Scanner, UpdateResult, DIMENSION};
 use super::node::Node;
 use super::Leaf;
 use crate::ebr::{AtomicShared, Guard, Ptr, Shared, Tag};
@@ -8,6 +8,7 @@ use crate::wait_queue::{DeriveAsyncWait, WaitQueue};
 use crate::LinkedList;
 use std::borrow::Borrow;
 use std::cmp::Ordering::{Equal, Greater, Less};
+use std::fmt::{self, Debug};
 use std::ops::{Bound, RangeBounds};
 use std::ptr;
 use std::sync::atomic::AtomicPtr;
@@ -113,6 +114,87 @@ impl<K, V> LeafNode<K, V> {
         self.unbounded_child.tag(Acquire) == RETIRED
     }
 
+    /// Returns a reference to the unbounded child [`Leaf`].
+    #[inline]
+    pub(super) fn unbounded_leaf<'g>(&self, guard: &'g Guard) -> Option<&'g Leaf<K, V>> {
+        self.unbounded_child.load(Acquire, guard).as_ref()
+    }
+
+    /// Visits every [`Leaf`] along with the child indexes from the node to the [`Leaf`].
+    #[inline]
+    pub(super) fn for_each_leaf<'g, F: FnMut(&[usize], &'g Leaf<K, V>)>(
+        &self,
+        path: &mut Vec<usize>,
+        f: &mut F,
+        guard: &'g Guard,
+    ) where
+        K: 'g,
+        V: 'g,
+    {
+        let mut index = 0;
+        for (_, child) in Scanner::new(&self.children) {
+            if let Some(leaf) = child.load(Acquire, guard).as_ref() {
+                path.push(index);
+                f(path, leaf);
+                path.pop();
+            }
+            index += 1;
+        }
+        if let Some(unbounded) = self.unbounded_child.load(Acquire, guard).as_ref() {
+            path.push(index);
+            f(path, unbounded);
+            path.pop();
+        }
+    }
+
+    /// Checks if the [`LeafNode`] has the same separators and leaves as the other one.
+    #[inline]
+    pub(super) fn structurally_eq(&self, other: &Self, guard: &Guard) -> bool
+    where
+        K: PartialEq,
+        V: PartialEq,
+    {
+        let leaf_eq = |c: &AtomicShared<Leaf<K, V>>, o: &AtomicShared<Leaf<K, V>>| match (
+            c.load(Acquire, guard).as_ref(),
+            o.load(Acquire, guard).as_ref(),
+        ) {
+            (Some(c), Some(o)) => Scanner::new(c).eq(Scanner::new(o)),
+            (None, None) => true,
+            _ => false,
+        };
+        let mut scanner = Scanner::new(&self.children);
+        let mut other_scanner = Scanner::new(&other.children);
+        loop {
+            match (scanner.next(), other_scanner.next()) {
+                (Some((key, leaf)), Some((other_key, other_leaf))) => {
+                    if key != other_key || !leaf_eq(leaf, other_leaf) {
+                        return false;
+                    }
+                }
+                (None, None) => break,
+                _ => return false,
+            }
+        }
+        leaf_eq(&self.unbounded_child, &other.unbounded_child)
+    }
+
+    /// Returns the separator keys and the number of child leaves.
+    #[inline]
+    pub(super) fn children<'g>(&'g self, guard: &'g Guard) -> (Vec<&'g K>, usize) {
+        let mut separators = Vec::new();
+        let mut num_leaves = 0;
+        for (key, child) in Scanner::new(&self.children) {
+            separators.push(key);
+            if !child.load(Acquire, guard).is_null() {
+                num_leaves += 1;
+            }
+        }
+        if !self.unbounded_child.load(Acquire, guard).is_null() {
+            num_leaves += 1;
+        }
+        (separators, num_leaves)
+    }
+
     /// Waits for the lock on the [`LeafNode`] to be released.
     #[inline]
     pub(super) fn wait<D: DeriveAsyncWait>(&self, async_wait: &mut D) {
@@ -200,6 +282,58 @@ where
         }
     }
 
+    /// Returns the number of nodes and the number of key comparisons that searching for the key
+    /// goes through.
+    #[inline]
+    pub(super) fn descent_profile<Q>(&self, key: &Q, guard: &Guard) -> (usize, usize)
+    where
+        K: Borrow<Q>,
+        Q: Ord + ?Sized,
+    {
+        let num_comparisons = self.children.num_comparisons(key);
+        let child = match self.children.min_greater_equal(key).0 {
+            Some((_, child)) => child.load(Acquire, guard),
+            None => self.unbounded_child.load(Acquire, guard),
+        };
+        let num_leaf_comparisons = child.as_ref().map_or(0, |leaf| leaf.num_comparisons(key));
+        (1, num_comparisons + num_leaf_comparisons)
+    }
+
+    /// Returns the leaf that the key belongs to along with the inclusive upper bound of the keys
+    /// that the leaf can contain.
+    #[inline]
+    pub(super) fn search_leaf<'g, Q>(
+        &'g self,
+        key: &Q,
+        bound: Option<&'g K>,
+        guard: &'g Guard,
+    ) -> Option<(&'g Leaf<K, V>, Option<&'g K>)>
+    where
+        K: Borrow<Q>,
+        Q: Ord + ?Sized,
+    {
+        loop {
+            let (child, metadata) = self.children.min_greater_equal(key);
+            if let Some((child_key, child)) = child {
+                if let Some(child) = child.load(Acquire, guard).as_ref() {
+                    if self.children.validate(metadata) {
+                        // Data race resolution - see `LeafNode::search`.
+                        return Some((child, Some(child_key)));
+                    }
+                }
+            } else {
+                let unbounded_ptr = self.unbounded_child.load(Acquire, guard);
+                if let Some(unbounded) = unbounded_ptr.as_ref() {
+                    if self.children.validate(metadata) {
+                        return Some((unbounded, bound));
+                    }
+                } else {
+                    return None;
+                }
+            }
+        }
+    }
+
     /// Returns the minimum key entry.
     #[inline]
     pub(super) fn min<'g>(&self, guard: &'g Guard) -> Option<Scanner<'g, K, V>> {
@@ -276,6 +410,44 @@ where
         None
     }
 
+    /// Locks the [`LeafNode`], and freezes all the [`Leaf`] instances in it.
+    ///
+    /// Entries in frozen [`Leaf`] instances cannot be inserted, removed, or updated, and threads
+    /// trying to do so wait for the returned [`Locker`] to be dropped.
+    ///
+    /// Returns `None` if the [`LeafNode`] is locked by another thread.
+    #[inline]
+    pub(super) fn freeze<'g>(&'g self, guard: &'g Guard) -> Option<Locker<'g, K, V>> {
+        let locker = Locker::try_lock(self)?;
+        for (_, child) in Scanner::new(&self.children) {
+            if let Some(leaf) = child.load(Acquire, guard).as_ref() {
+                leaf.freeze();
+            }
+        }
+
+        // A frozen `Leaf` is put in place of a missing unbounded child, otherwise
+        // `LeafNode::insert` would create a new one without acquiring the lock.
+        let mut unbounded_ptr = self.unbounded_child.load(Acquire, guard);
+        if unbounded_ptr.is_null() && unbounded_ptr.tag() == Tag::None {
+            let leaf = Leaf::new();
+            leaf.freeze();
+            match self.unbounded_child.compare_exchange(
+                Ptr::null(),
+                (Some(Shared::new(leaf)), Tag::None),
+                AcqRel,
+                Acquire,
+                guard,
+            ) {
+                Ok(_) => return Some(locker),
+                Err((_, actual)) => unbounded_ptr = actual,
+            }
+        }
+        if let Some(unbounded) = unbounded_ptr.as_ref() {
+            unbounded.freeze();
+        }
+        Some(locker)
+    }
+
     /// Inserts a key-value pair.
     ///
     /// # Errors
@@ -445,6 +617,102 @@ where
         }
     }
 
+    /// Updates the value associated with the given key.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if a retry is required.
+    #[inline]
+    pub(super) fn update<Q, F: FnMut(&V) -> Option<V>, D: DeriveAsyncWait>(
+        &self,
+        key: &Q,
+        updater: &mut F,
+        async_wait: &mut D,
+        guard: &Guard,
+    ) -> Result<UpdateResult<K, V>, ()>
+    where
+        K: Borrow<Q>,
+        Q: Ord + ?Sized,
+    {
+        loop {
+            let (child, metadata) = self.children.min_greater_equal(key);
+            if let Some((child_key, child)) = child {
+                let child_ptr = child.load(Acquire, guard);
+                if let Some(child_ref) = child_ptr.as_ref() {
+                    if self.children.validate(metadata) {
+                        // Data race resolution - see `LeafNode::search`.
+                        match child_ref.update(key, updater) {
+                            UpdateResult::Full(k, v) => {
+                                match self.split_leaf(
+                                    k,
+                                    v,
+                                    Some(child_key),
+                                    child_ptr,
+                                    child,
+                                    async_wait,
+                                    guard,
+                                ) {
+                                    Ok(InsertResult::Retry(..)) => continue,
+                                    Ok(InsertResult::Full(k, v)) => {
+                                        return Ok(UpdateResult::Full(k, v))
+                                    }
+                                    Ok(InsertResult::Retired(..)) => {
+                                        return Ok(UpdateResult::Retired)
+                                    }
+                                    _ => return Err(()),
+                                }
+                            }
+                            UpdateResult::Frozen => {
+                                // The `Leaf` is being split: retry.
+                                self.wait(async_wait);
+                                return Err(());
+                            }
+                            result => return Ok(result),
+                        }
+                    }
+                }
+                // It is not a hot loop - see `LeafNode::search`.
+                continue;
+            }
+
+            let unbounded_ptr = self.unbounded_child.load(Acquire, guard);
+            if let Some(unbounded) = unbounded_ptr.as_ref() {
+                debug_assert!(unbounded_ptr.tag() == Tag::None);
+                if !self.children.validate(metadata) {
+                    // Data race resolution - see `LeafNode::search`.
+                    continue;
+                }
+                match unbounded.update(key, updater) {
+                    UpdateResult::Full(k, v) => {
+                        match self.split_leaf(
+                            k,
+                            v,
+                            None,
+                            unbounded_ptr,
+                            &self.unbounded_child,
+                            async_wait,
+                            guard,
+                        ) {
+                            Ok(InsertResult::Retry(..)) => continue,
+                            Ok(InsertResult::Full(k, v)) => return Ok(UpdateResult::Full(k, v)),
+                            Ok(InsertResult::Retired(..)) => return Ok(UpdateResult::Retired),
+                            _ => return Err(()),
+                        }
+                    }
+                    UpdateResult::Frozen => {
+                        self.wait(async_wait);
+                        return Err(());
+                    }
+                    result => return Ok(result),
+                }
+            }
+            if unbounded_ptr.tag() == RETIRED {
+                return Ok(UpdateResult::Retired);
+            }
+            return Ok(UpdateResult::Fail);
+        }
+    }
+
     /// Removes a range of entries.
     ///
     /// Returns the number of remaining children.
@@ -534,6 +802,48 @@ where
         Ok(num_leaves)
     }
 
+    /// Counts the number of entries contained in the range and the total number of entries.
+    #[inline]
+    pub(super) fn estimate<R: RangeBounds<K>>(&self, range: &R, guard: &Guard) -> (f64, f64) {
+        let mut num_contained = 0;
+        let mut num_entries = 0;
+        for (_, child) in Scanner::new(&self.children) {
+            if let Some(leaf) = child.load(Acquire, guard).as_ref() {
+                let (contained, len) = leaf.count(range);
+                num_contained += contained;
+                num_entries += len;
+            }
+        }
+        if let Some(unbounded) = self.unbounded_child.load(Acquire, guard).as_ref() {
+            let (contained, len) = unbounded.count(range);
+            num_contained += contained;
+            num_entries += len;
+        }
+        (f64::from(num_contained), f64::from(num_entries))
+    }
+
+    /// Estimates the number of entries in the [`LeafNode`].
+    ///
+    /// It assumes that every leaf has as many entries as the first leaf.
+    #[inline]
+    pub(super) fn estimate_len(&self, guard: &Guard) -> f64 {
+        let mut num_leaves = 0_u32;
+        let mut first_leaf = None;
+        for (_, child) in Scanner::new(&self.children) {
+            if let Some(leaf) = child.load(Acquire, guard).as_ref() {
+                num_leaves += 1;
+                first_leaf.get_or_insert(leaf);
+            }
+        }
+        if let Some(unbounded) = self.unbounded_child.load(Acquire, guard).as_ref() {
+            num_leaves += 1;
+            first_leaf.get_or_insert(unbounded);
+        }
+        first_leaf.map_or(0.0, |leaf| {
+            f64::from(leaf.count(&(..)).1) * f64::from(num_leaves)
+        })
+    }
+
     /// Splits itself into the given leaf nodes, and returns the middle key value.
     #[allow(clippy::too_many_lines)]
     pub(super) fn split_leaf_node<'g>(
@@ -1048,6 +1358,48 @@ where
         }
         false
     }
+
+    /// Reverses the order of the separators in the [`LeafNode`].
+    #[cfg(test)]
+    pub(super) fn scramble_separators(&self) {
+        self.children.reverse_keys();
+    }
+
+    /// Checks if the keys in the [`LeafNode`] are in `(lower, upper]` and the separators bound
+    /// the keys in the leaves.
+    #[cfg(test)]
+    pub(super) fn validate<'g>(
+        &'g self,
+        mut lower: Option<&'g K>,
+        upper: Option<&'g K>,
+        guard: &'g Guard,
+    ) -> bool {
+        let leaf_in_bounds = |leaf: &Leaf<K, V>, lower: Option<&K>, upper: Option<&K>| {
+            let mut prev = lower;
+            Scanner::new(leaf).all(|(key, _)| {
+                let in_bounds = prev.map_or(true, |prev| prev < key)
+                    && upper.map_or(true, |upper| key <= upper);
+                prev.replace(key);
+                in_bounds
+            })
+        };
+        for (key, child) in Scanner::new(&self.children) {
+            if lower.map_or(false, |lower| lower >= key) || upper.map_or(false, |upper| key > upper)
+            {
+                return false;
+            }
+            if let Some(child) = child.load(Acquire, guard).as_ref() {
+                if !leaf_in_bounds(child, lower, Some(key)) {
+                    return false;
+                }
+            }
+            lower.replace(key);
+        }
+        self.unbounded_child
+            .load(Acquire, guard)
+            .as_ref()
+            .map_or(true, |unbounded| leaf_in_bounds(unbounded, lower, upper))
+    }
 }
 
 impl<'n, K, V> Locker<'n, K, V> {
@@ -1060,6 +1412,18 @@ impl<'n, K, V> Locker<'n, K, V> {
             None
         }
     }
+
+    /// Thaws all the [`Leaf`] instances frozen by [`LeafNode::freeze`].
+    #[inline]
+    pub(super) fn thaw(&self, guard: &Guard) {
+        self.leaf_node.for_each_leaf(
+            &mut Vec::new(),
+            &mut |_, leaf| {
+                leaf.thaw();
+            },
+            guard,
+        );
+    }
 }
 
 impl<'n, K, V> Drop for Locker<'n, K, V> {
@@ -1069,6 +1433,28 @@ impl<'n, K, V> Drop for Locker<'n, K, V> {
     }
 }
 
+impl<K: Debug, V> Debug for LeafNode<K, V> {
+    /// Formats the separator keys and whether a split is in progress.
+    #[inline]
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let separators = Scanner::new(&self.children)
+            .map(|(k, _)| k)
+            .collect::<Vec<_>>();
+        f.debug_struct("LeafNode")
+            .field("separators", &separators)
+            .field("unbounded_child", &!self.unbounded_child.is_null(Acquire))
+            .field(
+                "low_key_leaf",
+                &!self.split_op.low_key_leaf.is_null(Relaxed),
+            )
+            .field(
+                "high_key_leaf",
+                &!self.split_op.high_key_leaf.is_null(Relaxed),
+            )
+            .finish_non_exhaustive()
+    }
+}
+
 impl RemoveRangeState {
     /// Returns the next state.
     pub(super) fn next<K: Ord, R: RangeBounds<K>>(
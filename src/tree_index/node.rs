@@ -1,5 +1,5 @@
 use super::internal_node::{self, InternalNode};
-use super::leaf::{InsertResult, Leaf, RemoveResult, Scanner};
+use super::leaf::{InsertResult, Leaf, RemoveResult, Scanner, UpdateResult};
 use super::leaf_node::{self, LeafNode};
 use crate::ebr::{AtomicShared, Guard, Ptr, Shared, Tag};
 use crate::wait_queue::DeriveAsyncWait;
@@ -17,6 +17,15 @@ pub enum Node<K, V> {
     Leaf(LeafNode<K, V>),
 }
 
+/// [`Locker`] holds exclusive ownership of a [`Node`] locked by [`Node::freeze`].
+pub(super) enum Locker<'n, K, V> {
+    /// Locked internal node.
+    Internal(internal_node::Locker<'n, K, V>),
+
+    /// Locked and frozen leaf node.
+    Leaf(leaf_node::Locker<'n, K, V>),
+}
+
 impl<K, V> Node<K, V> {
     /// Creates a new [`InternalNode`].
     #[inline]
@@ -48,6 +57,72 @@ impl<K, V> Node<K, V> {
         }
     }
 
+    /// Visits every [`Leaf`] along with the child indexes from the node to the [`Leaf`].
+    #[inline]
+    pub(super) fn for_each_leaf<'g, F: FnMut(&[usize], &'g Leaf<K, V>)>(
+        &self,
+        path: &mut Vec<usize>,
+        f: &mut F,
+        guard: &'g Guard,
+    ) where
+        K: 'g,
+        V: 'g,
+    {
+        match &self {
+            Self::Internal(internal_node) => internal_node.for_each_leaf(path, f, guard),
+            Self::Leaf(leaf_node) => leaf_node.for_each_leaf(path, f, guard),
+        }
+    }
+
+    /// Checks if the node has the same structure and entries as the other one.
+    #[inline]
+    pub(super) fn structurally_eq(&self, other: &Self, guard: &Guard) -> bool
+    where
+        K: PartialEq,
+        V: PartialEq,
+    {
+        match (self, other) {
+            (Self::Internal(internal_node), Self::Internal(other_internal_node)) => {
+                internal_node.structurally_eq(other_internal_node, guard)
+            }
+            (Self::Leaf(leaf_node), Self::Leaf(other_leaf_node)) => {
+                leaf_node.structurally_eq(other_leaf_node, guard)
+            }
+            _ => false,
+        }
+    }
+
+    /// Returns the separator keys and the number of children, and pushes the child nodes into
+    /// `child_nodes` if the node is an internal node.
+    #[inline]
+    pub(super) fn children<'g>(
+        &'g self,
+        child_nodes: &mut Vec<&'g Node<K, V>>,
+        guard: &'g Guard,
+    ) -> (Vec<&'g K>, usize) {
+        match &self {
+            Self::Internal(internal_node) => {
+                let num_child_nodes = child_nodes.len();
+                let separators = internal_node.children(child_nodes, guard);
+                (separators, child_nodes.len() - num_child_nodes)
+            }
+            Self::Leaf(leaf_node) => leaf_node.children(guard),
+        }
+    }
+
+    /// Returns the [`Leaf`] reached by following unbounded children.
+    #[inline]
+    pub(super) fn unbounded_leaf<'g>(&self, guard: &'g Guard) -> Option<&'g Leaf<K, V>> {
+        match &self {
+            Self::Internal(internal_node) => internal_node
+                .unbounded_child
+                .load(Acquire, guard)
+                .as_ref()
+                .and_then(|child| child.unbounded_leaf(guard)),
+            Self::Leaf(leaf_node) => leaf_node.unbounded_leaf(guard),
+        }
+    }
+
     /// Checks if the node has retired.
     #[inline]
     pub(super) fn retired(&self) -> bool {
@@ -76,6 +151,43 @@ where
         }
     }
 
+    /// Returns the number of nodes and the number of key comparisons that searching for the key
+    /// goes through.
+    ///
+    /// This method is not linearizable.
+    #[inline]
+    pub(super) fn descent_profile<Q>(&self, key: &Q, guard: &Guard) -> (usize, usize)
+    where
+        K: Borrow<Q>,
+        Q: Ord + ?Sized,
+    {
+        match &self {
+            Self::Internal(internal_node) => internal_node.descent_profile(key, guard),
+            Self::Leaf(leaf_node) => leaf_node.descent_profile(key, guard),
+        }
+    }
+
+    /// Returns the leaf that the key belongs to along with the inclusive upper bound of the keys
+    /// that the leaf can contain.
+    ///
+    /// `None` as the upper bound means that the leaf is not bounded.
+    #[inline]
+    pub(super) fn search_leaf<'g, Q>(
+        &'g self,
+        key: &Q,
+        bound: Option<&'g K>,
+        guard: &'g Guard,
+    ) -> Option<(&'g Leaf<K, V>, Option<&'g K>)>
+    where
+        K: Borrow<Q>,
+        Q: Ord + ?Sized,
+    {
+        match &self {
+            Self::Internal(internal_node) => internal_node.search_leaf(key, bound, guard),
+            Self::Leaf(leaf_node) => leaf_node.search_leaf(key, bound, guard),
+        }
+    }
+
     /// Returns the minimum key-value pair.
     ///
     /// This method is not linearizable.
@@ -142,6 +254,26 @@ where
         }
     }
 
+    /// Updates the value associated with the given key.
+    #[inline]
+    pub(super) fn update<Q, F: FnMut(&V) -> Option<V>, D>(
+        &self,
+        key: &Q,
+        updater: &mut F,
+        async_wait: &mut D,
+        guard: &Guard,
+    ) -> Result<UpdateResult<K, V>, ()>
+    where
+        K: Borrow<Q>,
+        Q: Ord + ?Sized,
+        D: DeriveAsyncWait,
+    {
+        match &self {
+            Self::Internal(internal_node) => internal_node.update(key, updater, async_wait, guard),
+            Self::Leaf(leaf_node) => leaf_node.update(key, updater, async_wait, guard),
+        }
+    }
+
     /// Removes a range of entries.
     ///
     /// Returns the number of remaining children.
@@ -175,6 +307,24 @@ where
         }
     }
 
+    /// Estimates the number of entries contained in the range and the total number of entries.
+    #[inline]
+    pub(super) fn estimate<R: RangeBounds<K>>(&self, range: &R, guard: &Guard) -> (f64, f64) {
+        match &self {
+            Self::Internal(internal_node) => internal_node.estimate(range, guard),
+            Self::Leaf(leaf_node) => leaf_node.estimate(range, guard),
+        }
+    }
+
+    /// Estimates the number of entries in the node.
+    #[inline]
+    pub(super) fn estimate_len(&self, guard: &Guard) -> f64 {
+        match &self {
+            Self::Internal(internal_node) => internal_node.estimate_len(guard),
+            Self::Leaf(leaf_node) => leaf_node.estimate_len(guard),
+        }
+    }
+
     /// Splits the current root node.
     #[inline]
     pub(super) fn split_root(
@@ -241,6 +391,53 @@ where
         }
     }
 
+    /// Locks every node in the sub-tree, and freezes every [`Leaf`] in it.
+    ///
+    /// Nodes are locked from the top, and children are read only after their parent is locked,
+    /// therefore no structural changes can be made to the sub-tree once every node is locked.
+    /// Retired nodes are skipped since they do not have reachable entries.
+    ///
+    /// Returns `false` if a node in the sub-tree is locked by another thread, in which case the
+    /// nodes locked so far are left in `lockers`.
+    #[inline]
+    pub(super) fn freeze<'g>(
+        &'g self,
+        lockers: &mut Vec<Locker<'g, K, V>>,
+        guard: &'g Guard,
+    ) -> bool {
+        match &self {
+            Self::Internal(internal_node) => {
+                if internal_node.retired() {
+                    return true;
+                }
+                let Some(locker) = internal_node::Locker::try_lock(internal_node) else {
+                    return false;
+                };
+                lockers.push(Locker::Internal(locker));
+                Scanner::new(&internal_node.children).all(|(_, child)| {
+                    child
+                        .load(Acquire, guard)
+                        .as_ref()
+                        .map_or(true, |child| child.freeze(lockers, guard))
+                }) && internal_node
+                    .unbounded_child
+                    .load(Acquire, guard)
+                    .as_ref()
+                    .map_or(true, |unbounded| unbounded.freeze(lockers, guard))
+            }
+            Self::Leaf(leaf_node) => {
+                if leaf_node.retired() {
+                    return true;
+                }
+                let Some(locker) = leaf_node.freeze(guard) else {
+                    return false;
+                };
+                lockers.push(Locker::Leaf(locker));
+                true
+            }
+        }
+    }
+
     /// Cleans up or removes the current root node.
     ///
     /// If the root is empty, the root is removed from the tree, or if the root has only a single
@@ -358,14 +555,48 @@ where
             Self::Leaf(leaf_node) => leaf_node.cleanup_link(key, traverse_max, guard),
         }
     }
+
+    /// Reverses the order of the separators in the sub-tree, which corrupts the sub-tree.
+    #[cfg(test)]
+    pub(super) fn scramble_separators(&self, guard: &Guard) {
+        match &self {
+            Self::Internal(internal_node) => internal_node.scramble_separators(guard),
+            Self::Leaf(leaf_node) => leaf_node.scramble_separators(),
+        }
+    }
+
+    /// Checks if the keys in the sub-tree are in `(lower, upper]` and ordered by the separators.
+    #[cfg(test)]
+    pub(super) fn validate<'g>(
+        &'g self,
+        lower: Option<&'g K>,
+        upper: Option<&'g K>,
+        guard: &'g Guard,
+    ) -> bool {
+        match &self {
+            Self::Internal(internal_node) => internal_node.validate(lower, upper, guard),
+            Self::Leaf(leaf_node) => leaf_node.validate(lower, upper, guard),
+        }
+    }
+}
+
+impl<K, V> Locker<'_, K, V> {
+    /// Thaws the frozen [`Leaf`] instances, and releases the lock.
+    #[inline]
+    pub(super) fn thaw(self, guard: &Guard) {
+        match self {
+            Self::Internal(locker) => drop(locker),
+            Self::Leaf(locker) => locker.thaw(guard),
+        }
+    }
 }
 
-impl<K, V> Debug for Node<K, V> {
+impl<K: Debug, V> Debug for Node<K, V> {
     #[inline]
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         match self {
-            Self::Internal(_) => f.debug_tuple("Internal").finish(),
-            Self::Leaf(_) => f.debug_tuple("Leaf").finish(),
+            Self::Internal(internal_node) => internal_node.fmt(f),
+            Self::Leaf(leaf_node) => leaf_node.fmt(f),
         }
     }
 }